@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustdns::parser::dns::{
+    DNS,
+    TransportProto
+};
+
+// Any input has to end up as either a message or a parse error, never a panic
+fuzz_target!(|data: &[u8]| {
+    let _ = DNS::from(data, TransportProto::UDP);
+    let _ = DNS::from(data, TransportProto::TCP);
+});
@@ -0,0 +1,29 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustdns::parser::dns::{
+    DNS,
+    TransportProto
+};
+
+/*
+    Whatever parses has to serialize, the serialized message has to parse
+    again and serializing that has to give the same bytes
+*/
+fuzz_target!(|data: &[u8]| {
+    let message: DNS = match DNS::from(data, TransportProto::UDP) {
+        Ok(message) => message,
+        Err(..) => return
+    };
+
+    let bytes: Vec<u8> = message.bytes()
+        .expect("Parsed message failed to serialize");
+
+    let reparsed: DNS = DNS::from(&bytes, TransportProto::UDP)
+        .expect("Serialized message failed to parse");
+
+    assert_eq!(
+        reparsed.bytes().expect("Reparsed message failed to serialize"),
+        bytes
+    );
+});
@@ -1,24 +1,36 @@
 use crate::{
     CONFIG, 
     LOGGER, 
+    CACHEMANAGER,
+    MEMCACHE,
     cache::modules::rootserver::RootServerT,
+    cache::modules::memory::MemoryCacheT,
     parser::qtype::QuestionType
 };
-use tokio::sync::Mutex;
-use std::vec::IntoIter;
+use tokio::sync::Semaphore;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use super::modules::{
-    tld::fp_tlds, 
     rootserver::fetch_parse_rs_list, 
     rootserver::RootServer
 };
 use redis::{
-    Connection,
-    Commands,
+    aio::MultiplexedConnection,
+    AsyncCommands,
 };
 use slog::info;
 
 pub struct CacheManager {
-    pub redis_instance: Option<Mutex<Connection>>,
+    /// Clones of the connection share one socket, requests made through
+    /// them are pipelined and nobody waits for a lock
+    pub redis_instance: Option<MultiplexedConnection>,
+
+    /// Resources loaded by `load_resources`, kept for the life of the process
+    pub resources: HashMap<String, Vec<u8>>,
+
+    /// Writes to Redis that may be in flight at once, see `set`
+    writes: Arc<Semaphore>
 }
 
 #[async_trait::async_trait]
@@ -30,48 +42,51 @@ pub trait CMTrait {
     /// cache manager
     /// 
     /// Can return error in String format
-    fn connect(&mut self) -> Result<(), String>;
+    async fn connect(&mut self) -> Result<(), String>;
 
     /// Load resources from IANA
-    /// Resources are only loaded if the result is not already cached
+    /// Resources are kept in memory and only fetched if they are not already
+    /// cached in Redis, when it's connected
     /// 
     /// Helpers will fetch following resources and return them here to cache
     /// https://www.internic.net/domain/named.root
     /// https://www.internic.net/domain/root.zone
-    /// https://data.iana.org/TLD/tlds-alpha-by-domain.txt
     /// 
     /// Here are the formats resources are cached in ->
     /// 
-    /// 1. Top level domains -> TLD:<domain>
-    /// 
-    /// 2. Root servers -> ROOTS:<qtype> as a key and 
-    /// list of root servers separated by "\r" character in following 
-    /// format tld_ip/domain as value
+    /// 1. Root servers -> ROOTS:<qtype> as a key and 
+    /// list of root servers separated by " " character in following 
+    /// format ttl_type_address as value
     /// 
     /// Can return error in String format
     async fn load_resources(&mut self) -> Result<(), String>;
+
+    /// Value of `key` in Redis with the seconds it has left
+    async fn get_entry(&self, key: &str) -> Option<(Vec<u8>, u32)>;
+
+    /// Store `value` in Redis for `ttl` seconds
+    async fn set_entry(&self, key: String, value: Vec<u8>, ttl: u32);
 }
 
 #[async_trait::async_trait]
 impl CMTrait for CacheManager {
     fn new() -> CacheManager {
         CacheManager { 
-            redis_instance: None::<tokio::sync::Mutex<Connection>>,
+            redis_instance: None,
+            resources: HashMap::new(),
+            writes: Arc::new(Semaphore::new(REDIS_WRITES))
         }
     }
 
-    fn connect(&mut self) -> Result<(), String> {
-        let connection = redis::Client::open(&*CONFIG.cache.hostname)
-            .unwrap()
-            .get_connection();
-
-        match connection {
-            Ok(..) => {
-                return Ok(self.redis_instance = Some(
-                    Mutex::from(
-                        connection.expect("Failed to start redis cache")
-                    )
-                ));
+    async fn connect(&mut self) -> Result<(), String> {
+        let client: redis::Client = redis::Client::open(&*CONFIG.cache.hostname)
+            .map_err(|e| e.to_string())?;
+
+        match client.get_multiplexed_tokio_connection().await {
+            Ok(connection) => {
+                self.redis_instance = Some(connection);
+
+                return Ok(());
             },
 
             Err(e) => {
@@ -83,66 +98,142 @@ impl CMTrait for CacheManager {
     }
 
     async fn load_resources(&mut self) -> Result<(), String> {
-        let redis_c = self.redis_instance.as_mut()
-            .unwrap()
-            .get_mut();
+        const KEYS: [&str; 3] = ["ROOTS:NS", "ROOTS:A", "ROOTS:AAAA"];
 
-        match redis_c.get::<&str, Option<String>>("TLD:com").unwrap() {
-            Some(..) => {
+        if let Some(redis_c) = self.redis_instance.as_mut() {
+            let cached: Vec<Option<Vec<u8>>> = redis_c.get(&KEYS[..])
+                .await
+                .map_err(|e| e.to_string())?;
+
+            if cached.iter().all(Option::is_some) {
                 // Already cached
-                info!(LOGGER, "TLDs are already cached!");
-            },
+                info!(LOGGER, "Root servers are already cached!");
 
-            None => {
-                // Not cached
-                info!(LOGGER, "Caching TLDs...");
-                let tlds: IntoIter<String> = fp_tlds()
-                    .await
-                    .expect("Failed to fetch TLDs")
-                    .into_iter();
-
-                tlds.for_each(|item: String| {
-                    redis_c.set::<&str, String, String>(
-                        format!("TLD:{}", item).as_str(), 
-                        "exists".to_string()
-                    ).expect("Failed to set TLD");
-                });
+                self.resources = KEYS.iter()
+                    .map(|key: &&str| key.to_string())
+                    .zip(cached.into_iter().flatten())
+                    .collect();
+
+                return Ok(());
             }
         }
 
-        match redis_c.get::<&str, Option<String>>("ROOTS:NS").unwrap() {
-            Some(..) => {
-                // Already cached
-                info!(LOGGER, "Root servers are already cached!");
-            },
+        // Not cached
+        info!(LOGGER, "Loading root servers!");
+        let root_servers: Vec<RootServer> = fetch_parse_rs_list().await;
+        for item in root_servers {
+            let key: &str = match item.qtype {
+                QuestionType::NS => "ROOTS:NS",
+                QuestionType::A => "ROOTS:A",
+                QuestionType::AAAA  => "ROOTS:AAAA",
+                _ => continue
+            };
+
+            self.resources.entry(key.to_string())
+                .or_default()
+                .extend_from_slice(item.to_str().as_bytes());
+        }
 
-            None => {
-                // Not cached
-                info!(LOGGER, "Caching root servers!");
-                let root_servers: IntoIter<RootServer> = fetch_parse_rs_list()
+        if self.resources.is_empty() {
+            return Err(String::from("No root servers were loaded!"));
+        }
+
+        if let Some(redis_c) = self.redis_instance.as_mut() {
+            for (key, value) in &self.resources {
+                redis_c.set::<&str, &[u8], ()>(key, value)
                     .await
-                    .into_iter();
-
-                root_servers.for_each(|item: RootServer| {
-                    redis_c.append::<String, String, String>(
-                        match item.qtype {
-                            QuestionType::NS => "ROOTS:NS",
-                            QuestionType::A => "ROOTS:A",
-                            QuestionType::AAAA  => "ROOTS:AAAA",
-                            _ => "NS"
-                        }.to_string(), 
-                        item.to_str()
-                    )
-                    .is_err()
-                    .then(|| {
-                        return Result::Err::<(), String>(
-                            String::from("Failed to cache one of the root servers!")
-                        );
-                    });
-                });
+                    .map_err(|_| String::from("Failed to cache one of the root servers!"))?;
             }
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn get_entry(&self, key: &str) -> Option<(Vec<u8>, u32)> {
+        let mut redis_c: MultiplexedConnection = self.redis_instance.clone()?;
+
+        let value: Vec<u8> = redis_c.get::<&str, Option<Vec<u8>>>(key).await.ok()??;
+
+        // -2 means that the key expired since it was read
+        match redis_c.ttl::<&str, i64>(key).await.ok()? {
+            remaining if remaining > 0 => Some((value, remaining as u32)),
+            _ => None
+        }
+    }
+
+    async fn set_entry(&self, key: String, value: Vec<u8>, ttl: u32) {
+        if let Some(mut redis_c) = self.redis_instance.clone() {
+            let _ = redis_c.set_ex::<String, Vec<u8>, ()>(key, value, ttl as usize).await;
+        }
+    }
+}
+
+/// Writes to Redis in flight at once, more of them are dropped because the
+/// values are in memory already
+const REDIS_WRITES: usize = 4096;
+
+/// Keys missing in Redis aren't asked for again for this long
+const MISS_TTL: Duration = Duration::from_secs(5);
+
+/// Memory key remembering that `key` is missing in Redis
+fn miss_key(key: &str) -> String {
+    format!("MISS:{}", key)
+}
+
+/// Value of `key` with the seconds it has left, from memory or from Redis if
+/// it's turned on as the second tier. Values found in Redis are copied to
+/// memory and missing ones are remembered there, so the next lookup of the
+/// same key doesn't go to Redis
+pub async fn get(key: &str) -> Option<(Vec<u8>, u32)> {
+    if let Some((value, remaining)) = MEMCACHE.get(key) {
+        return Some((value, remaining.as_secs() as u32));
+    }
+
+    let manager: &CacheManager = CACHEMANAGER.get()?;
+    if manager.redis_instance.is_none() || MEMCACHE.get(&miss_key(key)).is_some() {
+        return None;
+    }
+
+    match manager.get_entry(key).await {
+        Some((value, remaining)) => {
+            MEMCACHE.set(key.to_string(), value.clone(), Duration::from_secs(remaining as u64));
+            Some((value, remaining))
+        },
+
+        None => {
+            MEMCACHE.set(miss_key(key), vec![], MISS_TTL);
+            None
+        }
+    }
+}
+
+/// Value of a resource loaded by `load_resources`
+pub async fn resource(key: &str) -> Option<Vec<u8>> {
+    CACHEMANAGER.get()?
+        .resources
+        .get(key)
+        .cloned()
+}
+
+/// Cache `value` for `ttl` seconds in memory and in Redis if it's turned on.
+/// Redis is written by a task of its own, so the tasks answering queries
+/// never wait for it
+pub async fn set(key: String, value: Vec<u8>, ttl: u32) {
+    // Redis refuses zero expiry, such values must not be cached anyway
+    if ttl == 0 {
+        return;
+    }
+
+    if let Some(manager) = CACHEMANAGER.get().filter(|manager: &&CacheManager| manager.redis_instance.is_some()) {
+        // Too many writes in flight only cost the copy in Redis
+        if let Ok(permit) = manager.writes.clone().try_acquire_owned() {
+            let (key, value) = (key.clone(), value.clone());
+            tokio::spawn(async move {
+                manager.set_entry(key, value, ttl).await;
+                drop(permit);
+            });
+        }
+    }
+
+    MEMCACHE.set(key, value, Duration::from_secs(ttl as u64));
+}
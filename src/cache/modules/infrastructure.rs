@@ -0,0 +1,284 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::{
+    IpAddr,
+    SocketAddr
+};
+use std::time::{
+    Duration,
+    Instant
+};
+
+/// Newest sample weighs 1/8 in the smoothed RTT, same as TCP does
+/// https://www.rfc-editor.org/rfc/rfc6298#section-2
+const SAMPLE_SHIFT: u32 = 3;
+
+/// Unknown servers get a random smoothed RTT below this many microseconds,
+/// so every one of them is tried once before the measured ones
+const UNKNOWN_SRTT: u64 = 10_000;
+
+/// Ceiling of the smoothed RTT in microseconds
+const MAX_SRTT: u64 = 10_000_000;
+
+/// Smoothed RTT and the failure count halve for every period without a new
+/// sample, so servers that were slow or down once are tried again eventually
+const DECAY_PERIOD: Duration = Duration::from_secs(60);
+
+/// Failures in a row after which a server is asked only when every other
+/// server was asked already, until the count decays below it again
+const MAX_FAILURES: u32 = 3;
+
+/// Chance in percent that a random server is asked first, keeps the
+/// estimates of the other servers fresh
+const EXPLORE_PERCENT: u32 = 5;
+
+/// Servers without a sample for this long are forgotten
+const ENTRY_TTL: Duration = Duration::from_secs(15 * 60);
+
+struct ServerStats {
+    /// Smoothed round trip time in microseconds
+    srtt: u64,
+
+    /// Failed queries since the last successful one
+    failures: u32,
+    updated: Instant
+}
+
+impl ServerStats {
+    fn periods(&self) -> u64 {
+        return self.updated.elapsed().as_secs() / DECAY_PERIOD.as_secs();
+    }
+
+    fn decayed_srtt(&self) -> u64 {
+        return self.srtt >> self.periods().min(63);
+    }
+
+    fn decayed_failures(&self) -> u32 {
+        return self.failures >> self.periods().min(31);
+    }
+}
+
+/// Round trip times and failures of upstream servers by their address,
+/// like the infrastructure cache of Unbound or the SRTT of BIND
+pub struct InfraCache {
+    servers: HashMap<IpAddr, ServerStats>
+}
+
+pub trait InfraCacheT {
+    /// Create an empty infrastructure cache
+    fn new() -> InfraCache;
+
+    /// Server answered after `rtt`
+    fn success(&mut self, address: IpAddr, rtt: Duration);
+
+    /// Server didn't answer usefully, its smoothed RTT is doubled and at
+    /// least `timeout`
+    fn failure(&mut self, address: IpAddr, timeout: Duration);
+
+    /// Servers of a zone ordered by which one should be asked first
+    fn order(&mut self, servers: &[SocketAddr]) -> Vec<SocketAddr>;
+}
+
+impl InfraCacheT for InfraCache {
+    fn new() -> InfraCache {
+        InfraCache {
+            servers: HashMap::new()
+        }
+    }
+
+    fn success(&mut self, address: IpAddr, rtt: Duration) {
+        let sample: u64 = (rtt.as_micros() as u64).min(MAX_SRTT);
+        let stats: &mut ServerStats = self.servers.entry(address)
+            .or_insert(ServerStats {
+                srtt: sample,
+                failures: 0,
+                updated: Instant::now()
+            });
+
+        // Sample is blended into what is left of the old estimate after decay
+        stats.srtt = stats.decayed_srtt();
+        stats.srtt = stats.srtt - (stats.srtt >> SAMPLE_SHIFT) + (sample >> SAMPLE_SHIFT);
+        stats.failures = 0;
+        stats.updated = Instant::now();
+    }
+
+    fn failure(&mut self, address: IpAddr, timeout: Duration) {
+        let timeout: u64 = (timeout.as_micros() as u64).min(MAX_SRTT);
+        let stats: &mut ServerStats = self.servers.entry(address)
+            .or_insert(ServerStats {
+                srtt: timeout,
+                failures: 0,
+                updated: Instant::now()
+            });
+
+        stats.srtt = (stats.decayed_srtt() * 2).max(timeout).min(MAX_SRTT);
+        stats.failures = stats.decayed_failures().saturating_add(1);
+        stats.updated = Instant::now();
+    }
+
+    fn order(&mut self, servers: &[SocketAddr]) -> Vec<SocketAddr> {
+        self.servers.retain(|_, stats: &mut ServerStats| stats.updated.elapsed() < ENTRY_TTL);
+
+        let mut rng = rand::thread_rng();
+        let mut scored: Vec<(bool, u64, SocketAddr)> = servers.iter()
+            .map(|server: &SocketAddr| match self.servers.get(&server.ip()) {
+                Some(stats) => (stats.decayed_failures() >= MAX_FAILURES, stats.decayed_srtt(), *server),
+                None => (false, rng.gen_range(0..UNKNOWN_SRTT), *server)
+            })
+            .collect();
+
+        scored.sort_by_key(|(dead, srtt, _)| (*dead, *srtt));
+
+        let mut ordered: Vec<SocketAddr> = scored.into_iter()
+            .map(|(_, _, server)| server)
+            .collect();
+
+        if ordered.len() > 1 && rng.gen_range(0..100) < EXPLORE_PERCENT {
+            let explored: SocketAddr = ordered.remove(rng.gen_range(1..ordered.len()));
+            ordered.insert(0, explored);
+        }
+
+        ordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(last: u8) -> SocketAddr {
+        return SocketAddr::from(([192, 0, 2, last], 53));
+    }
+
+    /// Stats last updated `periods` decay periods ago
+    fn aged(srtt: u64, failures: u32, periods: u32) -> ServerStats {
+        return ServerStats {
+            srtt,
+            failures,
+            updated: Instant::now().checked_sub(DECAY_PERIOD * periods).unwrap()
+        };
+    }
+
+    /// How often each server was asked first out of many orderings
+    fn firsts(cache: &mut InfraCache, servers: &[SocketAddr]) -> HashMap<SocketAddr, usize> {
+        let mut counts: HashMap<SocketAddr, usize> = HashMap::new();
+        for _ in 0..200 {
+            *counts.entry(cache.order(servers)[0]).or_default() += 1;
+        }
+
+        return counts;
+    }
+
+    #[test]
+    fn srtt_converges_to_samples() {
+        let mut cache: InfraCache = InfraCache::new();
+        let address: IpAddr = server(1).ip();
+
+        cache.success(address, Duration::from_millis(100));
+        assert_eq!(cache.servers[&address].srtt, 100_000);
+
+        // Each sample moves the estimate by an eighth of the difference
+        cache.success(address, Duration::from_millis(20));
+        assert_eq!(cache.servers[&address].srtt, 100_000 - 12_500 + 2_500);
+
+        for _ in 0..100 {
+            cache.success(address, Duration::from_millis(20));
+        }
+
+        let srtt: u64 = cache.servers[&address].srtt;
+        assert!((20_000..20_100).contains(&srtt), "{}", srtt);
+    }
+
+    #[test]
+    fn estimates_decay_before_new_samples() {
+        let mut cache: InfraCache = InfraCache::new();
+        let address: IpAddr = server(1).ip();
+
+        cache.servers.insert(address, aged(800_000, 0, 2));
+        assert_eq!(cache.servers[&address].decayed_srtt(), 200_000);
+
+        // Blended into the decayed estimate, not the stale one
+        cache.success(address, Duration::from_millis(200));
+        assert_eq!(cache.servers[&address].srtt, 200_000);
+
+        // Doubled from the decayed estimate as well
+        cache.servers.insert(address, aged(800_000, 2, 2));
+        cache.failure(address, Duration::from_millis(100));
+        assert_eq!(cache.servers[&address].srtt, 400_000);
+        assert_eq!(cache.servers[&address].failures, 1);
+    }
+
+    #[test]
+    fn failures_double_srtt_and_reset_on_success() {
+        let mut cache: InfraCache = InfraCache::new();
+        let address: IpAddr = server(1).ip();
+
+        cache.success(address, Duration::from_millis(100));
+        cache.failure(address, Duration::from_millis(500));
+        assert_eq!(cache.servers[&address].srtt, 500_000);
+
+        cache.failure(address, Duration::from_millis(500));
+        assert_eq!(cache.servers[&address].srtt, 1_000_000);
+        assert_eq!(cache.servers[&address].failures, 2);
+
+        for _ in 0..10 {
+            cache.failure(address, Duration::from_millis(500));
+        }
+        assert_eq!(cache.servers[&address].srtt, MAX_SRTT);
+
+        cache.success(address, Duration::from_millis(10));
+        assert_eq!(cache.servers[&address].failures, 0);
+    }
+
+    #[test]
+    fn faster_servers_are_asked_first() {
+        let mut cache: InfraCache = InfraCache::new();
+        let servers: [SocketAddr; 3] = [server(1), server(2), server(3)];
+
+        cache.success(servers[0].ip(), Duration::from_millis(300));
+        cache.success(servers[1].ip(), Duration::from_millis(30));
+        cache.success(servers[2].ip(), Duration::from_millis(100));
+
+        // Exploration asks another one first about one time in twenty
+        let counts: HashMap<SocketAddr, usize> = firsts(&mut cache, &servers);
+        assert!(counts[&servers[1]] > 150, "{:?}", counts);
+
+        // Unknown servers get a chance before any measured one
+        let unknown: SocketAddr = server(4);
+        let counts: HashMap<SocketAddr, usize> = firsts(&mut cache, &[servers[1], unknown]);
+        assert!(counts[&unknown] > 150, "{:?}", counts);
+    }
+
+    #[test]
+    fn dead_servers_are_asked_last() {
+        let mut cache: InfraCache = InfraCache::new();
+        let servers: [SocketAddr; 2] = [server(1), server(2)];
+
+        cache.success(servers[1].ip(), Duration::from_secs(2));
+        for _ in 0..MAX_FAILURES {
+            cache.failure(servers[0].ip(), Duration::from_millis(1));
+        }
+
+        // Its RTT is still far below the other one
+        assert_eq!(cache.servers[&servers[0].ip()].failures, MAX_FAILURES);
+        assert!(cache.servers[&servers[0].ip()].srtt < 100_000);
+
+        let counts: HashMap<SocketAddr, usize> = firsts(&mut cache, &servers);
+        assert!(counts[&servers[1]] > 150, "{:?}", counts);
+
+        // One decay period later it is below the threshold again
+        cache.servers.insert(servers[0].ip(), aged(1_000, MAX_FAILURES, 1));
+        let counts: HashMap<SocketAddr, usize> = firsts(&mut cache, &servers);
+        assert!(counts[&servers[0]] > 150, "{:?}", counts);
+    }
+
+    #[test]
+    fn stale_servers_are_forgotten() {
+        let mut cache: InfraCache = InfraCache::new();
+
+        cache.servers.insert(server(1).ip(), aged(1_000, 0, (ENTRY_TTL.as_secs() / DECAY_PERIOD.as_secs()) as u32));
+        cache.order(&[server(1)]);
+
+        assert!(cache.servers.is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::CONFIG;
+use std::collections::{
+    BTreeMap,
+    HashMap,
+    hash_map::DefaultHasher
+};
+use std::hash::{
+    Hash,
+    Hasher
+};
+use std::sync::Mutex;
+use std::time::{
+    Duration,
+    Instant
+};
+
+/// Independently locked parts of the cache, lookups of different keys
+/// rarely wait for each other
+const SHARDS: usize = 16;
+
+struct Entry {
+    value: Vec<u8>,
+    expires: Instant,
+
+    /// Tick of the last use, key of the entry in `Shard::recency`
+    used: u64
+}
+
+/// Least recently used entries are evicted once the shard is full
+struct Shard {
+    entries: HashMap<String, Entry>,
+
+    /// Keys ordered from the least recently used one
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    capacity: usize
+}
+
+impl Shard {
+    fn get(&mut self, key: &str) -> Option<(Vec<u8>, Duration)> {
+        let now: Instant = Instant::now();
+        let entry: &mut Entry = self.entries.get_mut(key)?;
+
+        if entry.expires <= now {
+            self.remove(key);
+            return None;
+        }
+
+        self.tick += 1;
+        self.recency.remove(&entry.used);
+        self.recency.insert(self.tick, key.to_string());
+        entry.used = self.tick;
+
+        Some((entry.value.clone(), entry.expires - now))
+    }
+
+    fn set(&mut self, key: String, value: Vec<u8>, ttl: Duration) {
+        self.remove(&key);
+
+        while self.entries.len() >= self.capacity {
+            match self.recency.pop_first() {
+                Some((_, oldest)) => self.entries.remove(&oldest),
+                None => break
+            };
+        }
+
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, Entry {
+            value,
+            expires: Instant::now() + ttl,
+            used: self.tick
+        });
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+/// Size bounded cache in the process, in front of Redis so hot names never
+/// leave it. Values are stored in the same form as in Redis
+pub struct MemoryCache {
+    shards: Vec<Mutex<Shard>>
+}
+
+pub trait MemoryCacheT {
+    /// Create an empty memory cache holding at most as many entries as the
+    /// config says
+    fn new() -> MemoryCache;
+
+    /// Value of `key` with the time it has left, None if it's not cached or
+    /// has expired
+    fn get(&self, key: &str) -> Option<(Vec<u8>, Duration)>;
+
+    /// Cache `value` for `ttl`, the least recently used entry of the shard
+    /// makes room for it if the shard is full
+    fn set(&self, key: String, value: Vec<u8>, ttl: Duration);
+}
+
+impl MemoryCache {
+    /// Cache of `SHARDS` shards holding `capacity` entries each
+    fn with_capacity(capacity: usize) -> MemoryCache {
+        MemoryCache {
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(Shard {
+                    entries: HashMap::new(),
+                    recency: BTreeMap::new(),
+                    tick: 0,
+                    capacity
+                }))
+                .collect()
+        }
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+}
+
+impl MemoryCacheT for MemoryCache {
+    fn new() -> MemoryCache {
+        MemoryCache::with_capacity((CONFIG.memory.max_entries / SHARDS).max(1))
+    }
+
+    fn get(&self, key: &str) -> Option<(Vec<u8>, Duration)> {
+        self.shard(key)
+            .lock()
+            .ok()?
+            .get(key)
+    }
+
+    fn set(&self, key: String, value: Vec<u8>, ttl: Duration) {
+        if let Ok(mut shard) = self.shard(&key).lock() {
+            shard.set(key, value, ttl);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn shard(capacity: usize) -> Shard {
+        return Shard {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            capacity
+        };
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut shard: Shard = shard(2);
+        shard.set(String::from("a"), vec![1], TTL);
+        shard.set(String::from("b"), vec![2], TTL);
+
+        // Reading "a" makes "b" the least recently used one
+        assert!(shard.get("a").is_some());
+        shard.set(String::from("c"), vec![3], TTL);
+
+        assert!(shard.get("b").is_none());
+        assert_eq!(shard.get("a").unwrap().0, vec![1]);
+        assert_eq!(shard.get("c").unwrap().0, vec![3]);
+        assert_eq!(shard.recency.len(), 2);
+    }
+
+    #[test]
+    fn setting_cached_key_replaces_it_without_eviction() {
+        let mut shard: Shard = shard(2);
+        shard.set(String::from("a"), vec![1], TTL);
+        shard.set(String::from("b"), vec![2], TTL);
+        shard.set(String::from("a"), vec![3], TTL);
+
+        assert_eq!(shard.get("a").unwrap().0, vec![3]);
+        assert_eq!(shard.get("b").unwrap().0, vec![2]);
+        assert_eq!(shard.entries.len(), shard.recency.len());
+    }
+
+    #[test]
+    fn expired_entry_is_removed_on_lookup() {
+        let mut shard: Shard = shard(2);
+        shard.set(String::from("a"), vec![1], Duration::ZERO);
+        shard.set(String::from("b"), vec![2], TTL);
+
+        assert!(shard.get("a").is_none());
+        assert!(!shard.entries.contains_key("a"));
+        assert_eq!(shard.recency.len(), 1);
+
+        let (_, remaining): (Vec<u8>, Duration) = shard.get("b").unwrap();
+        assert!(remaining <= TTL && remaining > TTL - Duration::from_secs(5));
+    }
+
+    #[test]
+    fn keys_are_spread_over_every_shard() {
+        let cache: MemoryCache = MemoryCache::with_capacity(1000);
+        for i in 0..1000 {
+            cache.set(format!("RR:{}.example.com:1:1", i), vec![], TTL);
+        }
+
+        let sizes: Vec<usize> = cache.shards.iter()
+            .map(|shard: &Mutex<Shard>| shard.lock().unwrap().entries.len())
+            .collect();
+
+        assert_eq!(sizes.iter().sum::<usize>(), 1000);
+        assert!(sizes.iter().all(|size: &usize| *size > 0));
+        assert!(cache.get("RR:42.example.com:1:1").is_some());
+    }
+
+    #[test]
+    fn full_shard_evicts_only_within_itself() {
+        let cache: MemoryCache = MemoryCache::with_capacity(1);
+        let keys: Vec<String> = (0..SHARDS * 4)
+            .map(|i: usize| format!("key{}", i))
+            .collect();
+
+        for key in &keys {
+            cache.set(key.clone(), vec![], TTL);
+        }
+
+        let cached: usize = keys.iter()
+            .filter(|key: &&String| cache.get(key).is_some())
+            .count();
+        let used: usize = cache.shards.iter()
+            .filter(|shard: &&Mutex<Shard>| !shard.lock().unwrap().entries.is_empty())
+            .count();
+
+        assert_eq!(cached, used);
+        assert!(keys.last().is_some_and(|key: &String| cache.get(key).is_some()));
+    }
+}
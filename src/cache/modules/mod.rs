@@ -1,2 +1,5 @@
 pub mod rootserver;
-pub mod tld;
\ No newline at end of file
+pub mod infrastructure;
+pub mod negative;
+pub mod rrset;
+pub mod memory;
\ No newline at end of file
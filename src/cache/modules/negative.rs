@@ -0,0 +1,149 @@
+use crate::parser::{
+    fqdn::FQDN,
+    qclass::QuestionClass,
+    qtype::QuestionType,
+    rdata::RData,
+    resource::DNSResourceFormat
+};
+use super::rrset;
+
+/// Negative answers are never kept longer than this, however long the zone
+/// asks for https://www.rfc-editor.org/rfc/rfc2308#section-5
+pub const MAX_NEGATIVE_TTL: u32 = 3 * 60 * 60;
+
+/// Redis key of a negative answer, a name that doesn't exist is cached for
+/// every type and a name without records of a type only for that type
+///
+/// 1. Name error -> NXDOMAIN:<name>
+///
+/// 2. No data -> NODATA:<name>:<qtype>:<class>
+///
+/// Type and class are numbers like in RRset keys. The SOA record proving the
+/// answer is the value, in wire format
+pub fn key(name: &FQDN, question: Option<(QuestionType, QuestionClass)>) -> String {
+    let name: String = name.to_string().to_lowercase();
+
+    match question {
+        Some((qtype, class)) => format!("NODATA:{}:{}:{}", name, u16::from(qtype), u16::from(class)),
+        None => format!("NXDOMAIN:{}", name)
+    }
+}
+
+/// SOA record from the authority section of a negative answer, answers
+/// without one can't be cached
+pub fn soa(authority: &[DNSResourceFormat]) -> Option<&DNSResourceFormat> {
+    authority.iter()
+        .find(|record: &&DNSResourceFormat| matches!(record.data, RData::SOA(..)))
+}
+
+/// Seconds a negative answer proven by `soa` is cached, the smaller of the
+/// SOA TTL and its MINIMUM field
+/// https://www.rfc-editor.org/rfc/rfc2308#section-3
+pub fn ttl(soa: &DNSResourceFormat) -> u32 {
+    let minimum: u32 = match &soa.data {
+        RData::SOA(soa) => soa.minimum,
+        _ => 0
+    };
+
+    soa.ttl.min(minimum).min(MAX_NEGATIVE_TTL)
+}
+
+/// SOA record as it's stored, in the same form as cached RRsets
+pub fn encode(soa: &DNSResourceFormat) -> Option<Vec<u8>> {
+    rrset::encode(std::slice::from_ref(soa))
+}
+
+/// SOA record back from its stored form, None if it's damaged. Clients must
+/// not keep the answer longer than the `remaining` seconds it has left here,
+/// so the TTL is lowered to them
+pub fn decode(bytes: &[u8], remaining: u32) -> Option<DNSResourceFormat> {
+    match rrset::decode(bytes)?.as_slice() {
+        [record] if matches!(record.data, RData::SOA(..)) => Some(DNSResourceFormat {
+            ttl: record.ttl.min(remaining),
+            ..record.clone()
+        }),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::rdata::StartOfAuthority;
+    use std::net::Ipv4Addr;
+
+    fn name(value: &str) -> FQDN {
+        return FQDN::try_from(value.to_string()).unwrap();
+    }
+
+    fn soa(ttl: u32, minimum: u32) -> DNSResourceFormat {
+        return DNSResourceFormat {
+            name: name("example.com"),
+            rr_type: QuestionType::SOA,
+            rr_class: QuestionClass::IN,
+            ttl,
+            data: RData::SOA(StartOfAuthority {
+                mname: name("ns.example.com"),
+                rname: name("hostmaster.example.com"),
+                serial: 1,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum
+            })
+        };
+    }
+
+    #[test]
+    fn keys_are_lowercase_with_numeric_type_and_class() {
+        assert_eq!(key(&name("WWW.Example.com"), None), "NXDOMAIN:www.example.com");
+        assert_eq!(
+            key(&name("WWW.Example.com"), Some((QuestionType::AAAA, QuestionClass::IN))),
+            "NODATA:www.example.com:28:1"
+        );
+        assert_ne!(
+            key(&name("example.com"), Some((QuestionType::A, QuestionClass::IN))),
+            key(&name("example.com"), Some((QuestionType::A, QuestionClass::CH)))
+        );
+    }
+
+    #[test]
+    fn ttl_is_smallest_of_soa_ttl_minimum_and_cap() {
+        assert_eq!(ttl(&soa(60, 300)), 60);
+        assert_eq!(ttl(&soa(3600, 300)), 300);
+        assert_eq!(ttl(&soa(86400, 86400)), MAX_NEGATIVE_TTL);
+    }
+
+    #[test]
+    fn soa_is_found_in_authority() {
+        let ns: DNSResourceFormat = DNSResourceFormat {
+            name: name("example.com"),
+            rr_type: QuestionType::A,
+            rr_class: QuestionClass::IN,
+            ttl: 300,
+            data: RData::A(Ipv4Addr::new(192, 0, 2, 1))
+        };
+
+        assert!(super::soa(std::slice::from_ref(&ns)).is_none());
+        assert_eq!(super::soa(&[ns, soa(60, 300)]).unwrap().ttl, 60);
+    }
+
+    #[test]
+    fn decoded_soa_ttl_is_lowered_to_remaining_time() {
+        let bytes: Vec<u8> = encode(&soa(300, 300)).unwrap();
+
+        assert_eq!(decode(&bytes, 1000).unwrap().ttl, 300);
+        assert_eq!(decode(&bytes, 42).unwrap().data, soa(300, 300).data);
+        assert_eq!(decode(&bytes, 42).unwrap().ttl, 42);
+    }
+
+    #[test]
+    fn damaged_or_other_records_are_not_decoded() {
+        let bytes: Vec<u8> = encode(&soa(300, 300)).unwrap();
+        let two: Vec<u8> = [bytes.clone(), bytes.clone()].concat();
+
+        assert!(decode(&bytes[..bytes.len() - 1], 300).is_none());
+        assert!(decode(&two, 300).is_none());
+        assert!(decode(&[], 300).is_none());
+    }
+}
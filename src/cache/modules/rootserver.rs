@@ -33,10 +33,10 @@ pub async fn fetch_parse_rs_list() -> Vec<RootServer> {
     split_f.pop().unwrap();
 
     let mut final_res: Vec<RootServer> = vec![];
-    for i in 0..split_f.len() - 1 {
-        let item = split_f[i].split(" ")
+    for line in &split_f[0..split_f.len() - 1] {
+        let item = line.split(' ')
             .filter(|el: &&str| {
-                !(el.len() == 0)
+                !el.is_empty()
             })
             .collect::<Vec<&str>>();
 
@@ -114,10 +114,8 @@ impl RootServerT for RootServer {
         };
         final_str += "_";
 
-        if self.domain.is_some() {
-            final_str += self.domain.as_ref()
-                .unwrap()
-                .as_str();
+        if let Some(domain) = self.domain.as_ref() {
+            final_str += domain.as_str();
         } else {
             final_str += self.ip.as_ref()
                 .unwrap()
@@ -0,0 +1,212 @@
+use crate::{
+    cache::def,
+    parser::{
+        fqdn::FQDN,
+        qclass::QuestionClass,
+        qtype::QuestionType,
+        resource::DNSResourceFormat,
+        writer::MessageWriter
+    }
+};
+use bitreader::BitReader;
+use std::collections::HashMap;
+
+/// Redis key of an RRset, RR:<name>:<qtype>:<class> with the numbers of the
+/// type and class
+pub fn key(name: &FQDN, qtype: QuestionType, class: QuestionClass) -> String {
+    format!(
+        "RR:{}:{}:{}",
+        name.to_string().to_lowercase(),
+        u16::from(qtype),
+        u16::from(class)
+    )
+}
+
+/// Key of the NS records a parent zone handed out in a referral to `cut`,
+/// DELEG:<cut>. They rank below the NS records of the zone itself, so they
+/// are only used to find servers and never served as an answer
+/// https://www.rfc-editor.org/rfc/rfc2181#section-5.4.1
+pub fn delegation_key(cut: &FQDN) -> String {
+    format!("DELEG:{}", cut.to_string().to_lowercase())
+}
+
+/// Records as they're stored, one after another in wire format. Names are
+/// never compressed because there is no message around them
+pub fn encode(records: &[DNSResourceFormat]) -> Option<Vec<u8>> {
+    let mut writer = MessageWriter::uncompressed();
+    for record in records {
+        record.bytes(&mut writer).ok()?;
+    }
+
+    Some(writer.finish())
+}
+
+/// Records back from their stored form, None if any of them is damaged
+pub fn decode(bytes: &[u8]) -> Option<Vec<DNSResourceFormat>> {
+    let mut reader = BitReader::new(bytes);
+    let mut records: Vec<DNSResourceFormat> = vec![];
+
+    while reader.remaining() > 0 {
+        records.push(DNSResourceFormat::from(&mut reader, bytes).ok()?);
+    }
+
+    Some(records)
+}
+
+/// Cached RRset of the name, type and class, the TTL of every record is the
+/// time the set has left in the cache
+pub async fn get(name: &FQDN, qtype: QuestionType, class: QuestionClass) -> Option<Vec<DNSResourceFormat>> {
+    load(&key(name, qtype, class)).await
+}
+
+/// NS records of the delegation to `cut` cached from a referral
+pub async fn get_delegation(cut: &FQDN) -> Option<Vec<DNSResourceFormat>> {
+    load(&delegation_key(cut)).await
+}
+
+/// Records stored under `key` with their TTLs aged
+async fn load(key: &str) -> Option<Vec<DNSResourceFormat>> {
+    let (bytes, remaining): (Vec<u8>, u32) = def::get(key).await?;
+
+    let mut records: Vec<DNSResourceFormat> = decode(&bytes)?;
+    age(&mut records, remaining);
+
+    (!records.is_empty()).then_some(records)
+}
+
+/// Lower the TTL of every record to the `remaining` seconds its set has left
+/// in the cache, clients must not keep them any longer
+fn age(records: &mut [DNSResourceFormat], remaining: u32) {
+    for record in records.iter_mut() {
+        record.ttl = record.ttl.min(remaining);
+    }
+}
+
+/// Cache every RRset in `records` until the smallest TTL in the set runs out
+pub async fn set(records: &[DNSResourceFormat]) {
+    let mut sets: HashMap<String, Vec<DNSResourceFormat>> = HashMap::new();
+    for record in records {
+        sets.entry(key(&record.name, record.rr_type, record.rr_class))
+            .or_default()
+            .push(record.clone());
+    }
+
+    for (key, records) in sets {
+        store(key, &records).await;
+    }
+}
+
+/// Cache the NS records of a referral to `cut` apart from the RRsets, see
+/// `delegation_key`
+pub async fn set_delegation(cut: &FQDN, records: &[DNSResourceFormat]) {
+    if !records.is_empty() {
+        store(delegation_key(cut), records).await;
+    }
+}
+
+/// Store `records` under `key` until the smallest TTL of them runs out
+async fn store(key: String, records: &[DNSResourceFormat]) {
+    let ttl: u32 = records.iter()
+        .map(|record: &DNSResourceFormat| record.ttl)
+        .min()
+        .unwrap_or_default();
+
+    if let Some(bytes) = encode(records) {
+        def::set(key, bytes, ttl).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::rdata::RData;
+    use std::net::Ipv4Addr;
+
+    fn name(value: &str) -> FQDN {
+        return FQDN::try_from(value.to_string()).unwrap();
+    }
+
+    fn record(owner: &str, ttl: u32, data: RData) -> DNSResourceFormat {
+        return DNSResourceFormat {
+            name: name(owner),
+            rr_type: match data {
+                RData::A(..) => QuestionType::A,
+                RData::NS(..) => QuestionType::NS,
+                _ => QuestionType::CNAME
+            },
+            rr_class: QuestionClass::IN,
+            ttl,
+            data
+        };
+    }
+
+    #[test]
+    fn keys_are_lowercase_with_numeric_type_and_class() {
+        assert_eq!(key(&name("WWW.Example.com"), QuestionType::AAAA, QuestionClass::IN), "RR:www.example.com:28:1");
+        assert_eq!(key(&FQDN::new(), QuestionType::NS, QuestionClass::CH), "RR:.:2:3");
+        assert_eq!(delegation_key(&name("Example.COM")), "DELEG:example.com");
+    }
+
+    #[tokio::test]
+    async fn delegations_are_kept_apart_from_answers() {
+        let zone: FQDN = name("deleg-test.example");
+        let answer: Vec<DNSResourceFormat> = vec![record("deleg-test.example", 3600, RData::NS(name("ns1.deleg-test.example")))];
+        let referral: Vec<DNSResourceFormat> = vec![record("deleg-test.example", 600, RData::NS(name("ns.parent.example")))];
+
+        // Parent side NS records are no answer to an NS question
+        set_delegation(&zone, &referral).await;
+        assert!(get(&zone, QuestionType::NS, QuestionClass::IN).await.is_none());
+
+        set(&answer).await;
+        set_delegation(&zone, &referral).await;
+
+        let answered: Vec<DNSResourceFormat> = get(&zone, QuestionType::NS, QuestionClass::IN).await.unwrap();
+        assert_eq!(answered[0].data, answer[0].data);
+
+        let delegated: Vec<DNSResourceFormat> = get_delegation(&zone).await.unwrap();
+        assert_eq!(delegated[0].data, referral[0].data);
+    }
+
+    #[test]
+    fn records_round_trip_uncompressed() {
+        let records: Vec<DNSResourceFormat> = vec![
+            record("www.example.com", 300, RData::CNAME(name("web.example.com"))),
+            record("web.example.com", 60, RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            record("web.example.com", 60, RData::A(Ipv4Addr::new(192, 0, 2, 2)))
+        ];
+
+        let bytes: Vec<u8> = encode(&records).unwrap();
+        let decoded: Vec<DNSResourceFormat> = decode(&bytes).unwrap();
+
+        // No pointers, the owner of every record is written in full
+        assert_eq!(bytes.len(), (17 + 10 + 17) + 2 * (17 + 10 + 4));
+        assert_eq!(decoded.len(), 3);
+
+        for (decoded, record) in decoded.iter().zip(records.iter()) {
+            assert_eq!(decoded.name, record.name);
+            assert_eq!(decoded.ttl, record.ttl);
+            assert_eq!(decoded.data, record.data);
+        }
+    }
+
+    #[test]
+    fn damaged_records_are_not_decoded() {
+        let bytes: Vec<u8> = encode(&[record("example.com", 60, RData::A(Ipv4Addr::LOCALHOST))]).unwrap();
+
+        assert!(decode(&bytes[..bytes.len() - 1]).is_none());
+        assert_eq!(decode(&[]).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn ttls_are_lowered_to_remaining_time() {
+        let mut records: Vec<DNSResourceFormat> = vec![
+            record("example.com", 300, RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            record("example.com", 30, RData::A(Ipv4Addr::new(192, 0, 2, 2)))
+        ];
+
+        age(&mut records, 100);
+
+        assert_eq!(records[0].ttl, 100);
+        assert_eq!(records[1].ttl, 30);
+    }
+}
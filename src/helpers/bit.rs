@@ -1,22 +1,18 @@
 #[macro_export]
-/// Assign given value based on if the provided unsigned integer 
+/// Assign given value based on if the next bit of the reader
 /// is one or zero
 /// 
-/// ### Panics
-/// - If the integer is not one or zero
+/// Returns early from the surrounding function with the reader
+/// error if there are no bits left
 macro_rules! bit_assign {
     ($is_zero:expr, $is_one:expr, $reader:expr) => {
-        match $reader.read_u8(1).unwrap() {
-            0 => {
+        match $reader.read_bool()? {
+            false => {
                 $is_zero
             }, 
     
-            1 => {
+            true => {
                 $is_one
-            },
-    
-            _ => {
-                panic!("Not valid bit!");
             }
         }
     };
@@ -29,6 +25,7 @@ macro_rules! bit_assign {
 /// 
 /// Example
 /// ```rust
+/// # use rustdns::convert_u16_to_two_u8s;
 /// let bytes: [u8; 2] = convert_u16_to_two_u8s!(100, u16);
 /// // Use these bytes
 /// bytes[0];
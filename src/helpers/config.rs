@@ -4,7 +4,8 @@ use serde::{
 };
 use std::{
     fs::File, 
-    io::Read
+    io::Read,
+    net::IpAddr
 };
 
 #[derive(Serialize, Deserialize)]
@@ -17,13 +18,61 @@ pub enum LogType {
 pub struct Config {
     pub host: Host,
     pub cache: Cache,
-    pub logging: Logging
+    pub logging: Logging,
+
+    #[serde(default)]
+    pub edns: Edns,
+
+    #[serde(default)]
+    pub resolver: Resolver,
+
+    #[serde(default)]
+    pub tcp: Tcp,
+
+    #[serde(default)]
+    pub memory: Memory
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Host {
     pub hostname: String,
-    pub port: u16
+    pub port: u16,
+
+    /// More addresses to listen on besides `hostname`, IPv6 included
+    #[serde(default)]
+    pub listen: Vec<String>
+}
+
+/// Address families used for upstream queries, the preferred one is asked
+/// first and the other one only when its servers fail
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    Dual,
+    PreferIpv4,
+    PreferIpv6,
+    Ipv4Only,
+    Ipv6Only
+}
+
+impl AddressFamily {
+    /// Whether servers on this address can be queried at all
+    pub fn allows(&self, address: &IpAddr) -> bool {
+        match self {
+            AddressFamily::Ipv4Only => address.is_ipv4(),
+            AddressFamily::Ipv6Only => address.is_ipv6(),
+            _ => true
+        }
+    }
+
+    /// Zero for addresses of the preferred family, sorting by it puts them first
+    pub fn rank(&self, address: &IpAddr) -> u8 {
+        match self {
+            AddressFamily::PreferIpv4 => address.is_ipv6() as u8,
+            AddressFamily::PreferIpv6 => address.is_ipv4() as u8,
+            _ => 0
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -38,6 +87,106 @@ pub struct Logging {
     pub file_path: Option<String>
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct Edns {
+    /// Buffer size advertised in OPT records and used for receiving UDP
+    /// messages, 1232 avoids IP fragmentation on almost every path
+    pub udp_payload_size: u16
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Edns {
+            udp_payload_size: 1232
+        }
+    }
+}
+
+/// Upstream queries, the first three are defences against spoofed responses
+/// https://www.rfc-editor.org/rfc/rfc5452
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Resolver {
+    /// Transaction ID of every upstream query is taken from the OS random
+    /// generator instead of a counter
+    pub random_ids: bool,
+
+    /// Upstream queries are sent from a random source port instead of the
+    /// one the OS picks
+    pub random_ports: bool,
+
+    /// Mix the case of the queried name and require the server to echo it
+    /// unchanged, off by default because a few servers don't
+    /// https://datatracker.ietf.org/doc/html/draft-vixie-dnsext-dns0x20-00
+    pub randomize_case: bool,
+
+    /// Time for the first attempt of a query, doubled for every retry
+    pub timeout_ms: u64,
+
+    /// Attempts per server before moving on to the next one
+    pub attempts: u8,
+
+    pub address_family: AddressFamily
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver {
+            random_ids: true,
+            random_ports: true,
+            randomize_case: false,
+            timeout_ms: 800,
+            attempts: 3,
+            address_family: AddressFamily::Dual
+        }
+    }
+}
+
+/// Client connections over TCP
+/// https://www.rfc-editor.org/rfc/rfc7766#section-6.2
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tcp {
+    /// Connections open at once, newer ones are closed right after accepting
+    pub max_connections: usize,
+
+    /// Connection is closed when no query arrives for this long
+    pub idle_timeout_ms: u64
+}
+
+impl Default for Tcp {
+    fn default() -> Self {
+        Tcp {
+            max_connections: 100,
+            idle_timeout_ms: 10_000
+        }
+    }
+}
+
+/// Cache in the process memory, Redis is the second tier behind it
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Memory {
+    /// Entries kept in memory, least recently used ones are evicted first
+    pub max_entries: usize,
+
+    /// Keep records and root servers in Redis as well, so they survive
+    /// restarts and can be shared by several instances. Redis isn't
+    /// connected to at all when this is off
+    pub redis_tier: bool
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory {
+            max_entries: 100_000,
+            redis_tier: true
+        }
+    }
+}
+
+// Inner errors are only read through the Debug output
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum ConfigError {
     TOMLERR(toml::de::Error),
@@ -0,0 +1,8 @@
+//! Wire format handling of rustdns, kept apart from the server binary so the
+//! parser can be fuzzed on its own
+
+#[macro_use]
+extern crate enum_primitive;
+
+pub mod parser;
+pub mod helpers;
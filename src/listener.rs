@@ -0,0 +1,419 @@
+use crate::{
+    CONFIG,
+    LOGGER,
+    resolver::handler::{
+        Handler,
+        HandlerT,
+        Responder
+    }
+};
+use rustdns::{
+    convert_u16_to_two_u8s,
+    convert_two_u8s_to_u16
+};
+use slog::{
+    crit,
+    info,
+    warn
+};
+use socket2::{
+    Domain,
+    Protocol,
+    Socket,
+    Type
+};
+use std::net::{
+    IpAddr,
+    SocketAddr
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncReadExt,
+    AsyncWriteExt
+};
+use tokio::net::{
+    TcpListener,
+    TcpStream,
+    UdpSocket,
+    tcp::{
+        OwnedReadHalf,
+        OwnedWriteHalf
+    }
+};
+use tokio::sync::{
+    mpsc,
+    OwnedSemaphorePermit,
+    Semaphore
+};
+
+/// Responses of one TCP connection waiting for the writer
+const TCP_RESPONSE_QUEUE: usize = 32;
+
+/// Queries of one TCP connection resolved at the same time, the next one is
+/// only read when one of them is answered
+const TCP_QUERIES_IN_FLIGHT: usize = 16;
+
+/// Pending TCP connections the OS keeps before they are accepted
+const TCP_BACKLOG: i32 = 1024;
+
+/// Bind UDP and TCP on every configured address and serve them until the
+/// process exits
+pub async fn run() {
+    let connections: Arc<Semaphore> = Arc::new(Semaphore::new(CONFIG.tcp.max_connections));
+
+    let mut listeners = vec![];
+    for address in listen_addresses() {
+        let socket: UdpSocket = match bind_udp(address) {
+            Ok(socket) => socket,
+            Err(e) => panic!("couldn't bind socket {}: {}", address, e)
+        };
+
+        let listener: TcpListener = match bind_tcp(address) {
+            Ok(listener) => listener,
+            Err(e) => panic!("couldn't bind listener {}: {}", address, e)
+        };
+
+        info!(
+            LOGGER,
+            "UDP socket and TCP listener are running!";
+            "host" => address.to_string()
+        );
+
+        listeners.push(tokio::task::spawn(serve_udp(Arc::new(socket))));
+        listeners.push(tokio::task::spawn(serve_tcp(listener, connections.clone())));
+    }
+
+    for listener in listeners {
+        let _ = listener.await;
+    }
+}
+
+/// Hostname and every extra address from the config, all on the same port
+fn listen_addresses() -> Vec<SocketAddr> {
+    std::iter::once(&CONFIG.host.hostname)
+        .chain(CONFIG.host.listen.iter())
+        .map(|hostname: &String| match hostname.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, CONFIG.host.port),
+            Err(..) => {
+                crit!(LOGGER, "Invalid listen address in config!"; "address" => hostname);
+
+                // This is done to prevent panic printing above the logger message
+                std::thread::sleep(Duration::from_millis(100));
+
+                panic!()
+            }
+        })
+        .collect()
+}
+
+/*
+    IPv6 sockets are bound as IPv6 only, otherwise they would take IPv4
+    traffic too and listening on :: and 0.0.0.0 at once would fail
+*/
+fn bind_socket(address: SocketAddr, kind: Type, protocol: Protocol) -> std::io::Result<Socket> {
+    let socket: Socket = Socket::new(Domain::for_address(address), kind, Some(protocol))?;
+
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    return Ok(socket);
+}
+
+fn bind_udp(address: SocketAddr) -> std::io::Result<UdpSocket> {
+    let socket: Socket = bind_socket(address, Type::DGRAM, Protocol::UDP)?;
+    socket.bind(&address.into())?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+fn bind_tcp(address: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket: Socket = bind_socket(address, Type::STREAM, Protocol::TCP)?;
+
+    // Restarted server can bind again while old connections are in TIME_WAIT
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(TCP_BACKLOG)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Receive queries on one socket forever, each one is handled in its own
+/// task and answered from the same socket
+async fn serve_udp(socket: Arc<UdpSocket>) {
+    loop {
+        // Clients using EDNS can send messages bigger than 512 bytes too
+        let mut buf: Vec<u8> = vec![0; CONFIG.edns.udp_payload_size.max(512) as usize];
+
+        match socket.recv_from(&mut buf).await {
+            Ok((amt, src)) => {
+                let socket: Arc<UdpSocket> = socket.clone();
+
+                tokio::task::spawn(async move {
+                    let mut handler: Handler = Handler::new();
+                    handler.responder = Some(Responder::UDP(socket));
+                    handler.handle(
+                        &buf[0..amt],
+                        src
+                    ).await;
+                });
+            },
+
+            Err(e) => {
+                warn!(LOGGER, "couldn't recieve a datagram"; "Error" => e.to_string());
+            }
+        }
+    }
+}
+
+/// Accept connections forever, connections over the configured limit are
+/// closed right away
+async fn serve_tcp(listener: TcpListener, connections: Arc<Semaphore>) {
+    loop {
+        let (stream, peer): (TcpStream, SocketAddr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!(LOGGER, "couldn't accept a connection"; "Error" => e.to_string());
+                continue;
+            }
+        };
+
+        let permit: OwnedSemaphorePermit = match connections.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(..) => {
+                warn!(LOGGER, "Too many TCP connections, closing"; "Sent from: " => peer.to_string());
+                continue;
+            }
+        };
+
+        tokio::task::spawn(serve_connection(stream, peer, permit));
+    }
+}
+
+/// Read pipelined queries from one connection until the client closes it
+/// or stays idle too long. Every query is handled in its own task, so
+/// responses are written in the order they are ready, not the order of the
+/// queries https://www.rfc-editor.org/rfc/rfc7766#section-6.2.1.1
+async fn serve_connection(stream: TcpStream, peer: SocketAddr, permit: OwnedSemaphorePermit) {
+    let (mut reader, writer): (OwnedReadHalf, OwnedWriteHalf) = stream.into_split();
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>(TCP_RESPONSE_QUEUE);
+    let in_flight: Arc<Semaphore> = Arc::new(Semaphore::new(TCP_QUERIES_IN_FLIGHT));
+
+    let writing = tokio::task::spawn(write_responses(writer, receiver));
+
+    loop {
+        // Nothing more is read while the connection has every query slot taken
+        let query: OwnedSemaphorePermit = match in_flight.clone().acquire_owned().await {
+            Ok(query) => query,
+            Err(..) => break
+        };
+
+        let message: Vec<u8> = match read_message(&mut reader).await {
+            Some(message) => message,
+            None => break
+        };
+
+        let sender: mpsc::Sender<Vec<u8>> = sender.clone();
+
+        tokio::task::spawn(async move {
+            let mut handler: Handler = Handler::new();
+            handler.responder = Some(Responder::TCP(sender));
+            handler.handle(
+                &message,
+                peer
+            ).await;
+
+            drop(query);
+        });
+    }
+
+    // Writer ends once every query that was read got its response
+    drop(sender);
+    let _ = writing.await;
+    drop(permit);
+}
+
+/// Next message with its length prefix, None when the connection is closed,
+/// broken or idle for longer than the configured timeout
+async fn read_message(reader: &mut OwnedReadHalf) -> Option<Vec<u8>> {
+    let idle: Duration = Duration::from_millis(CONFIG.tcp.idle_timeout_ms);
+
+    let mut prefix: [u8; 2] = [0; 2];
+    tokio::time::timeout(idle, reader.read_exact(&mut prefix))
+        .await
+        .ok()?
+        .ok()?;
+
+    let length: usize = convert_two_u8s_to_u16!(prefix[0], prefix[1]) as usize;
+    if length == 0 {
+        return None;
+    }
+
+    let mut message: Vec<u8> = vec![0; length + 2];
+    message[0..2].copy_from_slice(&prefix);
+
+    // Rest of a started message gets the same time again
+    tokio::time::timeout(idle, reader.read_exact(&mut message[2..]))
+        .await
+        .ok()?
+        .ok()?;
+
+    Some(message)
+}
+
+/// Write every response with its length prefix until all senders are gone
+async fn write_responses(mut writer: OwnedWriteHalf, mut receiver: mpsc::Receiver<Vec<u8>>) {
+    while let Some(response) = receiver.recv().await {
+        let length: u16 = match u16::try_from(response.len()) {
+            Ok(length) => length,
+            Err(..) => continue
+        };
+
+        let mut framed: Vec<u8> = convert_u16_to_two_u8s!(length, u16).to_vec();
+        framed.extend_from_slice(&response);
+
+        if writer.write_all(&framed).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{
+        dns::{DNS, TransportProto},
+        rcode::ResponseCode
+    };
+
+    /// Both ends of a local TCP connection, the client and the server side
+    async fn connection() -> (TcpStream, TcpStream) {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client: TcpStream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        return (client, server);
+    }
+
+    /// Message with its length prefix
+    fn framed(message: &[u8]) -> Vec<u8> {
+        let mut framed: Vec<u8> = (message.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(message);
+
+        return framed;
+    }
+
+    /// Next response on the client side without its prefix
+    async fn response(client: &mut TcpStream) -> DNS {
+        let mut prefix: [u8; 2] = [0; 2];
+        client.read_exact(&mut prefix).await.unwrap();
+
+        let mut message: Vec<u8> = vec![0; u16::from_be_bytes(prefix) as usize];
+        client.read_exact(&mut message).await.unwrap();
+
+        return DNS::from(&message, TransportProto::UDP).unwrap();
+    }
+
+    #[tokio::test]
+    async fn messages_split_across_reads() {
+        let (mut client, server) = connection().await;
+        let (mut reader, _writer): (OwnedReadHalf, OwnedWriteHalf) = server.into_split();
+
+        let first: Vec<u8> = framed(&[0xab; 12]);
+        let second: Vec<u8> = framed(&[0xcd; 20]);
+
+        tokio::task::spawn(async move {
+            // One byte of the prefix, then the rest of the first message with
+            // the start of the second one
+            let stream: Vec<u8> = [first.clone(), second.clone()].concat();
+            for chunk in [&stream[..1], &stream[1..20], &stream[20..]] {
+                client.write_all(chunk).await.unwrap();
+                client.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        assert_eq!(read_message(&mut reader).await.unwrap(), framed(&[0xab; 12]));
+        assert_eq!(read_message(&mut reader).await.unwrap(), framed(&[0xcd; 20]));
+        assert_eq!(read_message(&mut reader).await, None);
+    }
+
+    #[tokio::test]
+    async fn zero_length_and_cut_messages_end_the_connection() {
+        // What the client sends before closing its side
+        let table: Vec<Vec<u8>> = vec![
+            vec![0x00, 0x00, 0x01],
+            vec![0x00],
+            vec![0x00, 0x20, 0x01, 0x02],
+            vec![]
+        ];
+
+        for sent in table {
+            let (mut client, server) = connection().await;
+            let (mut reader, _writer): (OwnedReadHalf, OwnedWriteHalf) = server.into_split();
+
+            client.write_all(&sent).await.unwrap();
+            drop(client);
+
+            assert_eq!(read_message(&mut reader).await, None, "{:?}", sent);
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_are_written_in_the_order_they_are_ready() {
+        let (mut client, server) = connection().await;
+        let (_reader, writer): (OwnedReadHalf, OwnedWriteHalf) = server.into_split();
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>(TCP_RESPONSE_QUEUE);
+
+        tokio::task::spawn(write_responses(writer, receiver));
+
+        // First query is slower than the second one
+        for (id, delay) in [(1_u16, 60), (2, 0)] {
+            let sender: mpsc::Sender<Vec<u8>> = sender.clone();
+
+            tokio::task::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+
+                let mut datagram: DNS = DNS::new();
+                datagram.header.id = id;
+                sender.send(datagram.bytes().unwrap()).await.unwrap();
+            });
+        }
+
+        assert_eq!(response(&mut client).await.header.id, 2);
+        assert_eq!(response(&mut client).await.header.id, 1);
+    }
+
+    #[tokio::test]
+    async fn pipelined_queries_are_all_answered() {
+        let (mut client, server) = connection().await;
+        let peer: SocketAddr = client.local_addr().unwrap();
+        let permit: OwnedSemaphorePermit = Arc::new(Semaphore::new(1)).try_acquire_owned().unwrap();
+
+        tokio::task::spawn(serve_connection(server, peer, permit));
+
+        // UPDATE, NOTIFY and a query missing its question, all in one write
+        let queries: Vec<u8> = [
+            framed(&[0x00, 0x01, 0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            framed(&[0x00, 0x02, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            framed(&[0x00, 0x03, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00])
+        ].concat();
+        client.write_all(&queries).await.unwrap();
+
+        // Responses can come in any order, they're told apart by their ID
+        let mut codes: Vec<(u16, ResponseCode)> = vec![];
+        for _ in 0..3 {
+            let datagram: DNS = response(&mut client).await;
+            codes.push((datagram.header.id, datagram.response_code()));
+        }
+        codes.sort_by_key(|(id, _)| *id);
+
+        assert_eq!(codes, vec![
+            (1, ResponseCode::NotImplemented),
+            (2, ResponseCode::NotImplemented),
+            (3, ResponseCode::FormatError)
+        ]);
+    }
+}
@@ -3,22 +3,19 @@ extern crate enum_primitive;
 extern crate slog_async;
 extern crate slog_term;
 extern crate slog;
-extern crate bit;
 
 use crate::helpers::config::Config;
 use lazy_static::lazy_static;
-use resolver::handler::{Handler, HandlerT};
 use crate::cache::def::{
     CacheManager, CMTrait
 };
-use tokio::sync::{
-    MutexGuard, 
-    Mutex
+use crate::cache::modules::infrastructure::{
+    InfraCache, InfraCacheT
 };
-use std::net::{
-    SocketAddr, 
-    UdpSocket
+use crate::cache::modules::memory::{
+    MemoryCache, MemoryCacheT
 };
+use std::sync::OnceLock;
 use std::time::Duration;
 use slog::{
     o, 
@@ -27,9 +24,13 @@ use slog::{
     crit, warn
 };
 
-mod parser;
-mod helpers;
+use rustdns::{
+    parser,
+    helpers
+};
+
 mod cache;
+mod listener;
 mod resolver;
 
 lazy_static! {
@@ -74,70 +75,43 @@ lazy_static! {
         cfg
     };
 
-    pub static ref SOCKET: UdpSocket = {
-        let sckt = match UdpSocket::bind(
-            format!("{}:{}", CONFIG.host.hostname, CONFIG.host.port)
-                .as_str()
-                .parse::<SocketAddr>()
-                .unwrap()
-        ) {
-            Ok(s) => {
-                info!(
-                    LOGGER, 
-                    "UDP socket is running!";
-                    "host" => format!("{}:{}", CONFIG.host.hostname, CONFIG.host.port)
-                );
-                s
-            },
-            
-            Err(e) => panic!("couldn't bind socket: {}", e)
-        };
+    pub static ref INFRACACHE: std::sync::Mutex<InfraCache> = std::sync::Mutex::new(
+        InfraCache::new()
+    );
 
-        sckt
-    };
+    pub static ref MEMCACHE: MemoryCache = MemoryCache::new();
+}
+
+/// Set once in main before anything is resolved, lookups made without it
+/// only use the memory cache
+pub static CACHEMANAGER: OnceLock<CacheManager> = OnceLock::new();
 
-    pub static ref CACHEMANAGER: tokio::sync::Mutex<CacheManager> = Mutex::new({
-        let mut manager = CacheManager::new();
+#[tokio::main]
+async fn main() {
+    let mut manager: CacheManager = CacheManager::new();
 
-        match manager.connect() {
+    // Redis is only connected to when it's a tier of the cache
+    if CONFIG.memory.redis_tier {
+        match manager.connect().await {
             Ok(_) => info!(LOGGER, "Cache manager succefully started!"),
             Err(e) => {
                 crit!(LOGGER, "Failed to start cache manager!"; "Error" => e);
+
+                // This is done to prevent panic printing above the logger message
+                std::thread::sleep(Duration::from_millis(100));
+
                 panic!()
             }
         }
+    }
 
-        manager
-    });
-}
-
-#[tokio::main]
-async fn main() {
-    let mut current_cm: MutexGuard<CacheManager> = CACHEMANAGER.lock()
-        .await;
-
-    current_cm.load_resources()
+    manager.load_resources()
         .await
         .expect("Failed to load resources");
-    std::mem::drop(current_cm);
-
-    loop {
-        let mut buf = [0; 512];
-
-        match SOCKET.recv_from(&mut buf) {
-            Ok((amt, src)) => {
-                tokio::task::spawn(async move {
-                    Handler::new()
-                        .handle(
-                            &buf[0..amt],
-                            src
-                        ).await;
-                });
-            },
 
-            Err(e) => {
-                println!("couldn't recieve a datagram: {}", e);
-            }
-        }
+    if CACHEMANAGER.set(manager).is_err() {
+        unreachable!("Cache manager is only set here");
     }
-}
\ No newline at end of file
+
+    listener::run().await;
+}
@@ -1,5 +1,4 @@
 use bitreader::BitReader;
-use crate::resolver::transport::TransportProto;
 
 use super::{
     header::DNSHeader, 
@@ -7,16 +6,31 @@ use super::{
     resource::DNSResourceFormat, 
     rcode::ResponseCode, 
     opcode::OpCode, 
-    r#type::Type
+    r#type::Type,
+    qtype::QuestionType,
+    edns::{Edns, MIN_UDP_PAYLOAD},
+    error::ParseError,
+    writer::MessageWriter
 };
 
+/// Transport the message came over, TCP messages are framed with a two byte
+/// length prefix https://www.ietf.org/rfc/rfc1035.html#section-4.2.2
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TransportProto {
+    TCP,
+    UDP
+}
+
 #[derive(Debug)]
 pub struct DNS {
     pub header: DNSHeader,
     pub questions: Option<Vec<DNSQuestion>>,
     pub answer: Option<Vec<DNSResourceFormat>>,
     pub authority: Option<Vec<DNSResourceFormat>>,
-    pub additional: Option<Vec<DNSResourceFormat>>
+    pub additional: Option<Vec<DNSResourceFormat>>,
+
+    /// Contents of the OPT record, which is never part of `additional`
+    pub edns: Option<Edns>
 }
 
 impl DNS {
@@ -31,6 +45,9 @@ impl DNS {
                 truncated: false, 
                 recursion_desired: false, 
                 recursion_available: false, 
+                z: false,
+                authentic_data: false,
+                checking_disabled: false,
                 error_code: ResponseCode::NoError, 
                 question_count: 0, 
                 answer_count: 0, 
@@ -40,14 +57,29 @@ impl DNS {
             questions: None,
             answer: None,
             authority: None,
-            additional: None
+            additional: None,
+            edns: None
         }
     }
 
-    pub fn from(bytes: &[u8], proto: TransportProto) -> Result<DNS, ResponseCode> {
+    /// Parse the whole message, any malformed part fails the whole message
+    /// with a parse error, which is answered with a format error
+    pub fn from(bytes: &[u8], proto: TransportProto) -> Result<DNS, ParseError> {
+        let mut reader = BitReader::new(bytes);
+        let result = DNSHeader::try_from(&mut reader, proto)?;
+
+        /*
+            Compression pointers are offsets from the first octet of the header,
+            so the TCP length prefix is cut off and the rest of the message is
+            read from there
+        */
+        let bytes: &[u8] = match result.length {
+            Some(..) => &bytes[2..],
+            None => bytes
+        };
+
         let mut reader = BitReader::new(bytes);
-        let result = DNSHeader::try_from(&mut reader, proto)
-            .unwrap();
+        reader.skip(12 * 8)?;
 
         let mut questions = None;
         let mut answer = None;
@@ -56,8 +88,7 @@ impl DNS {
 
         if !result.truncated {
             questions = Some(
-                DNSQuestion::try_from(&mut reader, result.question_count)
-                    .unwrap()
+                DNSQuestion::try_from(&mut reader, bytes, result.question_count)?
             );
 
             answer = if result.answer_count > 0 {
@@ -65,8 +96,7 @@ impl DNS {
     
                 for _ in 0..result.answer_count {
                     res.push(
-                        DNSResourceFormat::from(&mut reader, bytes)
-                            .unwrap()
+                        DNSResourceFormat::from(&mut reader, bytes)?
                     );
                 }
     
@@ -80,8 +110,7 @@ impl DNS {
     
                 for _ in 0..result.authority_count {
                     res.push(
-                        DNSResourceFormat::from(&mut reader, bytes)
-                            .unwrap()
+                        DNSResourceFormat::from(&mut reader, bytes)?
                     );
                 }
     
@@ -95,8 +124,7 @@ impl DNS {
     
                 for _ in 0..result.additional_count {
                     res.push(
-                        DNSResourceFormat::from(&mut reader, bytes)
-                            .unwrap()
+                        DNSResourceFormat::from(&mut reader, bytes)?
                     );
                 }
     
@@ -106,21 +134,267 @@ impl DNS {
             };
         }
 
+        let edns: Option<Edns> = take_edns(&mut additional)?;
+
         Ok(DNS {
             header: result,
-            questions: questions,
-            answer: answer,
-            authority: authority,
-            additional: additional
+            questions,
+            answer,
+            authority,
+            additional,
+            edns
         })
     }
 
-    pub fn bytes(self) -> Result<Vec<u8>, ResponseCode> {
-        let mut bytes: Vec<u8> = vec![];
+    /// Serialize the whole message, section counts in the header are taken
+    /// from the sections themselves, whatever was set there before
+    pub fn bytes(mut self) -> Result<Vec<u8>, ResponseCode> {
+        self.write()
+    }
+
+    /// Serialize the message so it fits in `limit` octets, whole RRsets are
+    /// dropped from the end of the additional section first, then from the
+    /// authority and answer sections. The OPT record and the question are only
+    /// dropped when they don't fit on their own, leaving the bare header.
+    ///
+    /// Answer and authority RRsets are needed by the client, so dropping any
+    /// of them sets TC and the client asks again over TCP, dropping additional
+    /// records doesn't https://www.rfc-editor.org/rfc/rfc2181#section-9
+    pub fn bytes_within(mut self, limit: usize) -> Result<Vec<u8>, ResponseCode> {
+        loop {
+            let bytes: Vec<u8> = self.write()?;
+            if bytes.len() <= limit {
+                return Ok(bytes);
+            }
+
+            if drop_last_rrset(&mut self.additional) {
+                continue;
+            }
+
+            if !drop_last_rrset(&mut self.authority) && !drop_last_rrset(&mut self.answer) {
+                // Header, question and OPT record alone are over the limit
+                if self.edns.take().is_none() && self.questions.take().is_none() {
+                    return Err(ResponseCode::ServerFailure);
+                }
+            }
+
+            self.header.truncated = true;
+        }
+    }
+
+    fn write(&mut self) -> Result<Vec<u8>, ResponseCode> {
+        let opt: Option<DNSResourceFormat> = self.edns.as_ref()
+            .map(Edns::record);
+
+        self.header.question_count = section_count(&self.questions)?;
+        self.header.answer_count = section_count(&self.answer)?;
+        self.header.authority_count = section_count(&self.authority)?;
+        self.header.additional_count = section_count(&self.additional)?
+            .checked_add(opt.is_some() as u16)
+            .ok_or(ResponseCode::ServerFailure)?;
+
+        let mut writer = MessageWriter::new();
+
+        self.header.bytes(&mut writer);
+        DNSQuestion::bytes(&mut writer, self);
+
+        for section in [&self.answer, &self.authority, &self.additional] {
+            for record in section.iter().flatten() {
+                record.bytes(&mut writer)?;
+            }
+        }
+
+        if let Some(record) = &opt {
+            record.bytes(&mut writer)?;
+        }
+
+        Ok(writer.finish())
+    }
+
+    /// Full response code, the header only has the lower four bits of it
+    /// and the rest comes from the OPT record
+    pub fn response_code(&self) -> ResponseCode {
+        let upper: u16 = self.edns.as_ref()
+            .map_or(0, |edns: &Edns| edns.extended_rcode as u16);
 
-        DNSHeader::bytes(&mut bytes, &self);
-        DNSQuestion::bytes(&mut bytes, &self);
+        return ResponseCode::from(upper << 4 | u16::from(self.header.error_code));
+    }
+
+    /// Set the response code, codes above fifteen only survive if the
+    /// message carries an OPT record
+    pub fn set_response_code(&mut self, code: ResponseCode) {
+        self.header.error_code = match self.edns.as_mut() {
+            Some(edns) => edns.set_response_code(code),
+            None => ResponseCode::from(u16::from(code) & 0xF)
+        };
+    }
 
-        Ok(bytes)
+    /// Largest response the sender of this message can receive over UDP
+    pub fn max_udp_payload(&self) -> usize {
+        return self.edns.as_ref()
+            .map_or(MIN_UDP_PAYLOAD as usize, |edns: &Edns| edns.max_payload());
     }
-}
\ No newline at end of file
+}
+
+/// Take the OPT record out of the additional section, a message may carry
+/// at most one of them
+/// https://www.rfc-editor.org/rfc/rfc6891#section-6.1.1
+fn take_edns(additional: &mut Option<Vec<DNSResourceFormat>>) -> Result<Option<Edns>, ParseError> {
+    let records: Vec<DNSResourceFormat> = match additional.take() {
+        Some(records) => records,
+        None => return Ok(None)
+    };
+
+    let (opt, rest): (Vec<DNSResourceFormat>, Vec<DNSResourceFormat>) = records.into_iter()
+        .partition(|record: &DNSResourceFormat| record.rr_type == QuestionType::OPTION);
+
+    if !rest.is_empty() {
+        *additional = Some(rest);
+    }
+
+    match opt.as_slice() {
+        [] => Ok(None),
+        [record] => Ok(Some(Edns::from_record(record)?)),
+        _ => Err(ParseError::InvalidOpt)
+    }
+}
+
+/// Remove the RRset of the last record in the section, that is every record
+/// with the same owner, type and class. False if the section was empty
+fn drop_last_rrset(section: &mut Option<Vec<DNSResourceFormat>>) -> bool {
+    let last: DNSResourceFormat = match section.as_mut().and_then(Vec::pop) {
+        Some(record) => record,
+        None => return false
+    };
+
+    if let Some(records) = section.as_mut() {
+        records.retain(|record: &DNSResourceFormat| {
+            record.name != last.name || record.rr_type != last.rr_type || record.rr_class != last.rr_class
+        });
+
+        if records.is_empty() {
+            *section = None;
+        }
+    }
+
+    return true;
+}
+
+fn section_count<T>(section: &Option<Vec<T>>) -> Result<u16, ResponseCode> {
+    let length: usize = section.as_ref()
+        .map_or(0, |items: &Vec<T>| items.len());
+
+    u16::try_from(length)
+        .map_err(|_| ResponseCode::ServerFailure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{
+        fqdn::FQDN,
+        qclass::QuestionClass,
+        rdata::RData
+    };
+    use std::net::Ipv4Addr;
+
+    fn name(name: &str) -> FQDN {
+        FQDN::try_from(name.to_string()).unwrap()
+    }
+
+    /// `count` A records of `owner`, 16 octets each once the owner is
+    /// compressed
+    fn rrset(owner: &str, count: u8) -> Vec<DNSResourceFormat> {
+        (0..count)
+            .map(|i: u8| DNSResourceFormat {
+                name: name(owner),
+                rr_type: QuestionType::A,
+                rr_class: QuestionClass::IN,
+                ttl: 300,
+                data: RData::A(Ipv4Addr::new(192, 0, 2, i))
+            })
+            .collect()
+    }
+
+    fn response() -> DNS {
+        let mut datagram: DNS = DNS::new();
+        datagram.header.qr = Type::Response;
+        datagram.questions = Some(vec![DNSQuestion {
+            name: name("example.com"),
+            qtype: QuestionType::A,
+            class: QuestionClass::IN
+        }]);
+
+        datagram
+    }
+
+    #[test]
+    fn additional_rrsets_are_dropped_without_tc() {
+        let mut datagram: DNS = response();
+        datagram.answer = Some(rrset("example.com", 4));
+        datagram.additional = Some([rrset("a.example.com", 20), rrset("b.example.com", 20)].concat());
+        datagram.edns = Some(Edns::new(512));
+
+        let bytes: Vec<u8> = datagram.bytes_within(512).unwrap();
+        let parsed: DNS = DNS::from(&bytes, TransportProto::UDP).unwrap();
+
+        assert!(bytes.len() <= 512);
+        assert!(!parsed.header.truncated);
+        assert_eq!(parsed.answer.unwrap().len(), 4);
+        assert_eq!(parsed.additional.unwrap().len(), 20);
+        assert!(parsed.edns.is_some());
+    }
+
+    #[test]
+    fn answer_that_does_not_fit_sets_tc() {
+        let mut datagram: DNS = response();
+        datagram.answer = Some([rrset("example.com", 20), rrset("www.example.com", 20)].concat());
+        datagram.additional = Some(rrset("a.example.com", 1));
+
+        let bytes: Vec<u8> = datagram.bytes_within(512).unwrap();
+
+        // Sections of truncated messages are not parsed, only the header
+        let parsed: DNS = DNS::from(&bytes, TransportProto::UDP).unwrap();
+
+        assert!(bytes.len() <= 512);
+        assert!(parsed.header.truncated);
+        assert_eq!(parsed.header.answer_count, 20);
+        assert_eq!(parsed.header.additional_count, 0);
+    }
+
+    #[test]
+    fn question_and_opt_over_limit_leave_header_with_tc() {
+        let mut datagram: DNS = response();
+        datagram.questions = Some(vec![DNSQuestion {
+            name: name(&["a".repeat(63), "b".repeat(63), "c".repeat(63)].join(".")),
+            qtype: QuestionType::A,
+            class: QuestionClass::IN
+        }]);
+        datagram.answer = Some(rrset("example.com", 1));
+        datagram.edns = Some(Edns::new(512));
+
+        let bytes: Vec<u8> = datagram.bytes_within(100).unwrap();
+        let parsed: DNS = DNS::from(&bytes, TransportProto::UDP).unwrap();
+
+        assert_eq!(bytes.len(), 12);
+        assert!(parsed.header.truncated);
+        assert_eq!(parsed.header.question_count, 0);
+        assert_eq!(parsed.header.additional_count, 0);
+    }
+
+    #[test]
+    fn limit_below_header_fails() {
+        assert_eq!(response().bytes_within(11).unwrap_err(), ResponseCode::ServerFailure);
+    }
+
+    #[test]
+    fn message_within_limit_is_unchanged() {
+        let mut datagram: DNS = response();
+        datagram.answer = Some(rrset("example.com", 2));
+
+        let mut expected: DNS = response();
+        expected.answer = Some(rrset("example.com", 2));
+
+        assert_eq!(datagram.bytes_within(512).unwrap(), expected.bytes().unwrap());
+    }
+}
@@ -0,0 +1,195 @@
+use super::{
+    error::ParseError,
+    fqdn::FQDN,
+    qclass::QuestionClass,
+    qtype::QuestionType,
+    rcode::ResponseCode,
+    rdata::RData,
+    resource::DNSResourceFormat
+};
+
+/// Largest message every DNS implementation has to accept over UDP, also
+/// the floor for sizes advertised in OPT records
+/// https://www.rfc-editor.org/rfc/rfc6891#section-6.2.5
+pub const MIN_UDP_PAYLOAD: u16 = 512;
+
+/// Mask of the DO bit in the flags part of the OPT record TTL
+/// https://www.rfc-editor.org/rfc/rfc3225#section-3
+const DO: u32 = 0x8000;
+
+/// One option from the OPT record RDATA, code and data are kept as received
+/// so options this server knows nothing about are still passed along
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>
+}
+
+/// Everything the OPT pseudo record carries, the record itself is taken out
+/// of the additional section when the message is parsed and put back when
+/// it is serialized
+/// https://www.rfc-editor.org/rfc/rfc6891#section-6.1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    /// Largest UDP message the sender can reassemble
+    pub udp_payload_size: u16,
+
+    /// Upper eight bits of the twelve bit response code, the lower four
+    /// bits stay in the header
+    pub extended_rcode: u8,
+    pub version: u8,
+
+    /// DNSSEC records are wanted in the response
+    pub dnssec_ok: bool,
+
+    /// Remaining flag bits, must be zero but are carried as received
+    pub z: u16,
+    pub options: Vec<EdnsOption>
+}
+
+impl Edns {
+    /// Version 0 without any flags or options
+    pub fn new(udp_payload_size: u16) -> Self {
+        Edns {
+            udp_payload_size,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            z: 0,
+            options: vec![]
+        }
+    }
+
+    /// Read the OPT record fields, the record has to be owned by the root
+    /// name
+    pub fn from_record(record: &DNSResourceFormat) -> Result<Self, ParseError> {
+        if !record.name.labels().is_empty() {
+            return Err(ParseError::InvalidOpt);
+        }
+
+        let options: Vec<EdnsOption> = match &record.data {
+            RData::OPT(options) => options.clone(),
+            _ => return Err(ParseError::InvalidOpt)
+        };
+
+        Ok(Edns {
+            udp_payload_size: u16::from(record.rr_class),
+            extended_rcode: (record.ttl >> 24) as u8,
+            version: (record.ttl >> 16) as u8,
+            dnssec_ok: record.ttl & DO != 0,
+            z: (record.ttl & 0x7FFF) as u16,
+            options
+        })
+    }
+
+    /// OPT record to be appended to the additional section
+    pub fn record(&self) -> DNSResourceFormat {
+        let mut ttl: u32 = (self.extended_rcode as u32) << 24 |
+            (self.version as u32) << 16 |
+            (self.z & 0x7FFF) as u32;
+
+        if self.dnssec_ok {
+            ttl |= DO;
+        }
+
+        DNSResourceFormat {
+            name: FQDN::new(),
+            rr_type: QuestionType::OPTION,
+            rr_class: QuestionClass::from(self.udp_payload_size),
+            ttl,
+            data: RData::OPT(self.options.clone())
+        }
+    }
+
+    /// Advertised payload size, anything below 512 is treated as 512
+    pub fn max_payload(&self) -> usize {
+        return self.udp_payload_size.max(MIN_UDP_PAYLOAD) as usize;
+    }
+
+    /// Split a full response code into the upper bits kept here and the
+    /// four bits that go into the header
+    pub fn set_response_code(&mut self, code: ResponseCode) -> ResponseCode {
+        let code: u16 = u16::from(code);
+        self.extended_rcode = (code >> 4) as u8;
+
+        return ResponseCode::from(code & 0xF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::dns::{DNS, TransportProto};
+
+    /// Query for example.com A with DO set, a 1232 byte buffer and a client
+    /// cookie, as sent by dig
+    const QUERY: [u8; 52] = [
+        0x12, 0x34, 0x01, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01,
+        0x00, 0x00, 0x29, 0x04, 0xD0, 0x00, 0x00, 0x80, 0x00, 0x00, 0x0C,
+        0x00, 0x0A, 0x00, 0x08, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08
+    ];
+
+    #[test]
+    fn opt_record_is_taken_out_of_additional() {
+        let datagram: DNS = DNS::from(&QUERY, TransportProto::UDP).unwrap();
+        let edns: &Edns = datagram.edns.as_ref().unwrap();
+
+        assert!(datagram.additional.is_none());
+        assert_eq!(edns.udp_payload_size, 1232);
+        assert_eq!(edns.version, 0);
+        assert!(edns.dnssec_ok);
+        assert_eq!(edns.options, vec![EdnsOption {
+            code: 10,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8]
+        }]);
+
+        assert_eq!(datagram.bytes().unwrap(), QUERY);
+    }
+
+    #[test]
+    fn extended_rcode_is_split_between_header_and_opt() {
+        let mut datagram: DNS = DNS::new();
+        datagram.edns = Some(Edns::new(4096));
+        datagram.set_response_code(ResponseCode::BadVersion);
+
+        assert_eq!(datagram.header.error_code, ResponseCode::NoError);
+        assert_eq!(datagram.edns.as_ref().unwrap().extended_rcode, 1);
+
+        let bytes: Vec<u8> = datagram.bytes().unwrap();
+        let parsed: DNS = DNS::from(&bytes, TransportProto::UDP).unwrap();
+        assert_eq!(parsed.response_code(), ResponseCode::BadVersion);
+        assert_eq!(parsed.max_udp_payload(), 4096);
+    }
+
+    #[test]
+    fn small_payload_size_is_raised_to_512() {
+        assert_eq!(Edns::new(100).max_payload(), 512);
+        assert_eq!(DNS::new().max_udp_payload(), 512);
+    }
+
+    #[test]
+    fn second_opt_record_is_rejected() {
+        let mut bytes: Vec<u8> = QUERY.to_vec();
+        bytes[11] = 2;
+        bytes.extend_from_slice(&QUERY[29..]);
+
+        assert_eq!(
+            DNS::from(&bytes, TransportProto::UDP).unwrap_err(),
+            ParseError::InvalidOpt
+        );
+    }
+
+    #[test]
+    fn opt_record_outside_root_is_rejected() {
+        let mut bytes: Vec<u8> = QUERY[..29].to_vec();
+        bytes.extend_from_slice(&[0xC0, 0x0C]);
+        bytes.extend_from_slice(&QUERY[30..]);
+
+        assert_eq!(
+            DNS::from(&bytes, TransportProto::UDP).unwrap_err(),
+            ParseError::InvalidOpt
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use bitreader::BitReaderError;
+use super::rcode::ResponseCode;
+
+/// Reason a message couldn't be parsed, every one of them is answered with
+/// a format error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseError {
+    /// Message ended before a field could be read
+    UnexpectedEnd,
+
+    /// Label type 0b01 or 0b10, those are extended or reserved
+    InvalidLabelType,
+
+    /// Compression pointer that doesn't point backwards or too many of them
+    InvalidPointer,
+
+    /// Label longer than 63 octets or empty label in the middle of a name
+    InvalidLabel,
+
+    /// Name longer than 255 octets on the wire
+    NameTooLong,
+
+    /// Malformed escape in the presentation format of a name
+    InvalidEscape,
+
+    /// RDATA fields don't add up to RDLENGTH
+    RDataLength,
+
+    /// Field value not allowed by the record type
+    InvalidValue,
+
+    /// OPT record owned by another name than root, or more than one of them
+    InvalidOpt
+}
+
+impl From<BitReaderError> for ParseError {
+    fn from(_: BitReaderError) -> Self {
+        ParseError::UnexpectedEnd
+    }
+}
+
+impl From<ParseError> for ResponseCode {
+    fn from(_: ParseError) -> Self {
+        ResponseCode::FormatError
+    }
+}
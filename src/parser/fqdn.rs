@@ -1,101 +1,283 @@
-use super::rcode::ResponseCode;
+use std::fmt;
+use std::hash::{
+    Hash,
+    Hasher
+};
+use super::error::ParseError;
 
+/// Longest domain name in wire format including length octets
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Longest single label, two upper bits of the length octet are flags
+pub const MAX_LABEL_LENGTH: usize = 63;
+
+/// Most labels a name can have without exceeding `MAX_NAME_LENGTH`
+pub const MAX_LABELS: usize = 127;
+
+/// Fully qualified domain name as a sequence of labels, the root label is
+/// implied at the end
+/// https://www.rfc-editor.org/rfc/rfc1034#section-3.1
+///
+/// Labels are arbitrary octets, comparison and hashing ignore ASCII case
 #[derive(Debug, Clone)]
 pub struct FQDN {
-    pub subdomain: Option<String>,
-    pub domain_name: String,
-    pub tld: String
+    labels: Vec<Vec<u8>>
 }
 
 impl FQDN {
+    /// The root name
     pub fn new() -> FQDN {
-        FQDN { 
-            subdomain: None, 
-            domain_name: String::from(""), 
-            tld: String::from("") 
+        FQDN {
+            labels: vec![]
         }
     }
 
-    pub fn to_string(&self) -> String {
-        if self.subdomain.is_none() {
-            return String::from(format!(
-                "{}.{}",
-                self.domain_name,
-                self.tld
-            ))
-        } else {
-            return String::from(format!(
-                "{}.{}.{}",
-                self.subdomain.as_ref().unwrap(),
-                self.domain_name,
-                self.tld
-            ))
+    /// Build name from labels ordered from the leftmost one, fails if any
+    /// label or the whole name exceeds the limits from RFC 1035 section 2.3.4
+    pub fn from_labels(labels: Vec<Vec<u8>>) -> Result<FQDN, ParseError> {
+        if labels.len() > MAX_LABELS {
+            return Err(ParseError::NameTooLong);
         }
+
+        for label in &labels {
+            if label.is_empty() || label.len() > MAX_LABEL_LENGTH {
+                return Err(ParseError::InvalidLabel);
+            }
+        }
+
+        let name = FQDN { labels };
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(ParseError::NameTooLong);
+        }
+
+        Ok(name)
     }
 
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    /// Length of the uncompressed name on the wire, including every length
+    /// octet and the terminating root label, so it's never zero
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
-        if self.subdomain.is_none() {
-            return self.domain_name.len() +
-                self.tld.len();
-        } else {
-            return self.domain_name.len() +
-                self.subdomain.as_ref().unwrap().len() +
-                self.tld.len();
+        self.labels.iter()
+            .map(|label: &Vec<u8>| label.len() + 1)
+            .sum::<usize>() + 1
+    }
+
+    /// True if the name is `zone` itself or anywhere below it, every name is
+    /// a subdomain of the root
+    pub fn is_subdomain_of(&self, zone: &FQDN) -> bool {
+        if zone.labels.len() > self.labels.len() {
+            return false;
+        }
+
+        self.labels[self.labels.len() - zone.labels.len()..].iter()
+            .zip(zone.labels.iter())
+            .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// Top level domain in lowercase, None for the root name
+    pub fn tld(&self) -> Option<String> {
+        self.labels.last()
+            .map(|label: &Vec<u8>| {
+                String::from_utf8_lossy(label).to_lowercase()
+            })
+    }
+}
+
+impl PartialEq for FQDN {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len() &&
+            self.labels.iter()
+                .zip(other.labels.iter())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for FQDN {}
+
+impl Hash for FQDN {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for label in &self.labels {
+            label.to_ascii_lowercase().hash(state);
         }
     }
+}
 
-    pub fn split(&self) -> Vec<String> {
-        if self.subdomain.is_none() {
-            vec![
-                self.domain_name.clone(),
-                self.tld.clone()
-            ] 
-        } else {
-            vec![
-                self.subdomain.as_ref().unwrap().clone(),
-                self.domain_name.clone(),
-                self.tld.clone()
-            ]
+/// Presentation format without the trailing dot, root is a single dot.
+/// Dots and backslashes inside labels are escaped, unprintable octets are
+/// written as \DDD
+/// https://www.ietf.org/rfc/rfc1035.html#section-5.1
+impl fmt::Display for FQDN {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.labels.is_empty() {
+            return write!(f, ".");
         }
+
+        for (i, label) in self.labels.iter().enumerate() {
+            if i != 0 {
+                write!(f, ".")?;
+            }
+
+            for byte in label {
+                match byte {
+                    b'.' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                    0x21..=0x7E => write!(f, "{}", *byte as char)?,
+                    _ => write!(f, "\\{:03}", byte)?
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
+/// Parse presentation format, the trailing dot is optional and escapes
+/// written by the Display implementation are understood
 impl TryFrom<String> for FQDN {
-    type Error = ResponseCode;
+    type Error = ParseError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        let splitted = value.split(".")
-            .map(|item| {
-                item.to_string()
-            })
-            .collect::<Vec<String>>();
-
-        match splitted.len() {
-            2 => {
-                return Ok(
-                    FQDN { 
-                        subdomain: None, 
-                        domain_name: splitted[0].clone(),
-                        tld: splitted[1].clone()
+        if value.is_empty() || value == "." {
+            return Ok(FQDN::new());
+        }
+
+        let mut labels: Vec<Vec<u8>> = vec![];
+        let mut label: Vec<u8> = vec![];
+        let mut bytes = value.bytes().peekable();
+
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'.' => {
+                    labels.push(std::mem::take(&mut label));
+
+                    // Trailing dot of the root label
+                    if bytes.peek().is_none() {
+                        return FQDN::from_labels(labels);
                     }
-                )
-            },
-
-            3 => {
-                return Ok(
-                    FQDN {
-                        subdomain: Some(splitted[0].clone()),
-                        domain_name: splitted[1].clone(),
-                        tld: splitted[2].clone()
+                },
+
+                b'\\' => {
+                    let next: u8 = bytes.next()
+                        .ok_or(ParseError::InvalidEscape)?;
+
+                    if next.is_ascii_digit() {
+                        let digits: [u8; 3] = [
+                            next,
+                            bytes.next().ok_or(ParseError::InvalidEscape)?,
+                            bytes.next().ok_or(ParseError::InvalidEscape)?
+                        ];
+
+                        let value: u8 = std::str::from_utf8(&digits)
+                            .ok()
+                            .and_then(|digits: &str| digits.parse::<u8>().ok())
+                            .ok_or(ParseError::InvalidEscape)?;
+
+                        label.push(value);
+                    } else {
+                        label.push(next);
                     }
-                )
-            },
+                },
 
-            _ => {
-                return Err::<Self, Self::Error>(
-                    ResponseCode::FormatError
-                );
+                _ => label.push(byte)
             }
         }
+
+        labels.push(label);
+        FQDN::from_labels(labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(value: &str) -> FQDN {
+        return FQDN::try_from(value.to_string()).unwrap();
+    }
+
+    #[test]
+    fn root_has_no_labels() {
+        assert_eq!(name("."), FQDN::new());
+        assert_eq!(name(""), FQDN::new());
+        assert_eq!(FQDN::new().to_string(), ".");
+        assert_eq!(FQDN::new().len(), 1);
+    }
+
+    #[test]
+    fn length_counts_length_octets_and_root_label() {
+        assert_eq!(name("www.example.com").len(), 17);
+        assert_eq!(name("www.example.com.").len(), 17);
+    }
+
+    #[test]
+    fn label_limits_are_enforced() {
+        assert!(FQDN::from_labels(vec![vec![b'a'; MAX_LABEL_LENGTH]]).is_ok());
+        assert_eq!(
+            FQDN::from_labels(vec![vec![b'a'; MAX_LABEL_LENGTH + 1]]).unwrap_err(),
+            ParseError::InvalidLabel
+        );
+        assert_eq!(
+            FQDN::try_from(String::from("www..com")).unwrap_err(),
+            ParseError::InvalidLabel
+        );
+    }
+
+    #[test]
+    fn name_length_limit_is_enforced() {
+        let longest: Vec<Vec<u8>> = vec![
+            vec![b'a'; 63], vec![b'a'; 63], vec![b'a'; 63], vec![b'a'; 61]
+        ];
+        let too_long: Vec<Vec<u8>> = vec![
+            vec![b'a'; 63], vec![b'a'; 63], vec![b'a'; 63], vec![b'a'; 62]
+        ];
+
+        assert_eq!(FQDN::from_labels(longest).unwrap().len(), MAX_NAME_LENGTH);
+        assert_eq!(FQDN::from_labels(too_long).unwrap_err(), ParseError::NameTooLong);
+        assert_eq!(
+            FQDN::from_labels(vec![vec![b'a']; MAX_LABELS + 1]).unwrap_err(),
+            ParseError::NameTooLong
+        );
+    }
+
+    #[test]
+    fn dots_backslashes_and_unprintable_octets_are_escaped() {
+        let labels: Vec<Vec<u8>> = vec![b"a.b\\c".to_vec(), vec![0x00, b' ', 0xFF]];
+        let fqdn: FQDN = FQDN::from_labels(labels.clone()).unwrap();
+
+        assert_eq!(fqdn.to_string(), "a\\.b\\\\c.\\000\\032\\255");
+        assert_eq!(name(&fqdn.to_string()).labels(), &labels[..]);
+    }
+
+    #[test]
+    fn malformed_escapes_are_rejected() {
+        assert_eq!(FQDN::try_from(String::from("a\\")).unwrap_err(), ParseError::InvalidEscape);
+        assert_eq!(FQDN::try_from(String::from("a\\25")).unwrap_err(), ParseError::InvalidEscape);
+        assert_eq!(FQDN::try_from(String::from("a\\256")).unwrap_err(), ParseError::InvalidEscape);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn comparison_and_hashing_ignore_case() {
+        use std::collections::HashSet;
+
+        assert_eq!(name("WWW.Example.COM"), name("www.example.com"));
+        assert_ne!(name("www.example.com"), name("www.example.org"));
+
+        let set: HashSet<FQDN> = [name("Example.COM")].into_iter().collect();
+        assert!(set.contains(&name("example.com")));
+    }
+
+    #[test]
+    fn subdomains_and_tld() {
+        assert!(name("www.Example.com").is_subdomain_of(&name("example.COM")));
+        assert!(name("example.com").is_subdomain_of(&name("example.com")));
+        assert!(name("example.com").is_subdomain_of(&FQDN::new()));
+        assert!(!name("example.com").is_subdomain_of(&name("www.example.com")));
+        assert!(!name("badexample.com").is_subdomain_of(&name("example.com")));
+
+        assert_eq!(name("www.example.COM").tld(), Some(String::from("com")));
+        assert_eq!(FQDN::new().tld(), None);
+    }
+}
@@ -1,18 +1,27 @@
-use bit::BitIndex;
 use bitreader::BitReader;
-use enum_primitive::FromPrimitive;
-use crate::{
-    convert_u16_to_two_u8s, 
-    bit_assign,
-    push_byte_vec, resolver::transport::TransportProto
-};
 
 use super::{
     r#type::Type, 
     opcode::OpCode, 
-    rcode::ResponseCode, dns::DNS
+    rcode::ResponseCode, dns::TransportProto,
+    error::ParseError,
+    writer::MessageWriter
 };
 
+/// Masks of the single bit flags in the second header word
+/// https://www.rfc-editor.org/rfc/rfc6895#section-2
+const QR: u16 = 0x8000;
+const AA: u16 = 0x0400;
+const TC: u16 = 0x0200;
+const RD: u16 = 0x0100;
+const RA: u16 = 0x0080;
+const Z: u16 = 0x0040;
+const AD: u16 = 0x0020;
+const CD: u16 = 0x0010;
+
+const OPCODE_SHIFT: u16 = 11;
+const CODE_MASK: u16 = 0xF;
+
 #[derive(Debug)]
 pub struct DNSHeader {
     pub length: Option<u16>,
@@ -23,6 +32,15 @@ pub struct DNSHeader {
     pub truncated: bool,
     pub recursion_desired: bool,
     pub recursion_available: bool,
+
+    /// Reserved bit, must be zero but is carried as received
+    pub z: bool,
+
+    /// https://www.rfc-editor.org/rfc/rfc4035#section-3.2.3
+    pub authentic_data: bool,
+
+    /// https://www.rfc-editor.org/rfc/rfc4035#section-3.2.2
+    pub checking_disabled: bool,
     pub error_code: ResponseCode,
     pub question_count: u16,
     pub answer_count: u16,
@@ -41,6 +59,9 @@ impl DNSHeader {
             truncated: false, 
             recursion_desired: true, 
             recursion_available: false, 
+            z: false,
+            authentic_data: false,
+            checking_disabled: false,
             error_code: ResponseCode::NoError, 
             question_count: 0, 
             answer_count: 0, 
@@ -49,135 +70,172 @@ impl DNSHeader {
         }
     }
 
-    pub fn try_from(reader: &mut BitReader, proto: TransportProto) -> Result<Self, ResponseCode> {
+    pub fn try_from(reader: &mut BitReader, proto: TransportProto) -> Result<Self, ParseError> {
         let mut result = DNSHeader::new();
 
         // TCP messages are prefixed with two-byte length header
         if proto == TransportProto::TCP {
             result.length = Some(
-                reader.read_u16(16).unwrap()
+                reader.read_u16(16)?
             );
         };
 
-        result.id = reader.read_u16(16).unwrap();
-
-        result.qr = bit_assign!(
-            Type::Query, 
-            Type::Response,
-            reader
-        );
-
-        result.op_code = OpCode::from_u8(reader.read_u8(4).unwrap())
-            .unwrap();
-
-        result.authoritative = bit_assign!(
-            false, 
-            true, 
-            reader
-        );
-
-        result.truncated = bit_assign!(
-            false, 
-            true, 
-            reader
-        );
-
-        result.recursion_desired = bit_assign!(
-            false, 
-            true, 
-            reader
-        );
-
-        result.recursion_available = bit_assign!(
-            false, 
-            true, 
-            reader
-        );
-
-        reader.skip(3).unwrap();
-
-        result.error_code = ResponseCode::from_u8(
-            reader.read_u8(4).unwrap()
-        ).unwrap();
-
-        result.question_count = reader.read_u16(16).unwrap();
-        result.answer_count = reader.read_u16(16).unwrap();
-        result.authority_count = reader.read_u16(16).unwrap();
-        result.additional_count = reader.read_u16(16).unwrap();
+        result.id = reader.read_u16(16)?;
+        result.set_flags(reader.read_u16(16)?);
+
+        result.question_count = reader.read_u16(16)?;
+        result.answer_count = reader.read_u16(16)?;
+        result.authority_count = reader.read_u16(16)?;
+        result.additional_count = reader.read_u16(16)?;
         
         Ok(result)
     }
 
-    pub fn bytes(bytes: &mut Vec<u8>, datagram: &DNS) {
-        push_byte_vec!(bytes, 2, 0x0);
-        let id_u8: [u8; 2] = convert_u16_to_two_u8s!(datagram.header.id, u16);
-        bytes[0] = id_u8[0];
-        bytes[1] = id_u8[1];
-        
-        // Question or response
-        push_byte_vec!(bytes, 1, 0x0);
-        bytes[2].set_bit(
-            7, 
-            if datagram.header.qr == Type::Query {
-                false
-            } else {
-                true
-            },
-        );
-    
-        // Opcode
-        let opc_bits: u8 = datagram.header.op_code.try_into()
-            .unwrap();
-        bytes[2].set_bit_range(3..6, opc_bits);
-    
-        // Authoritative Answer
-        bytes[2].set_bit(
-            2, 
-            datagram.header.authoritative
-        );
-    
-        // If the message was truncated
-        bytes[2].set_bit(
-            1, 
-            datagram.header.truncated
-        );
-    
-        // If recursion will be used
-        bytes[2].set_bit(
-            0, 
-            datagram.header.recursion_desired
-        );
-    
-        // If recursion is available
-        push_byte_vec!(bytes, 1, 0x0);
-        bytes[3].set_bit(
-            7, 
-            datagram.header.recursion_available
-        );
-    
-        // Skipped 3 bits because of the section that will be used in future
-        let rcode_bits: u8 = datagram.header.error_code.try_into()
-            .unwrap();
-        bytes[3].set_bit_range(0..3, rcode_bits);
-    
-        push_byte_vec!(bytes, 2, 0x0);
-        let q_bits: [u8; 2] = convert_u16_to_two_u8s!(datagram.header.question_count, u16);
-        bytes[4].set_bit_range(0..7, q_bits[0]);
-        bytes[5].set_bit_range(0..7, q_bits[1]);
-    
-        push_byte_vec!(bytes, 2, 0x0);
-        let an_bits: [u8; 2] = convert_u16_to_two_u8s!(datagram.header.answer_count, u16);
-        bytes[6].set_bit_range(0..7, an_bits[0]);
-        bytes[7].set_bit_range(0..7, an_bits[1]);
-    
-        push_byte_vec!(bytes, 2, 0x0);
-        let ns_bits: [u8; 2] = convert_u16_to_two_u8s!(datagram.header.authority_count, u16);
-        bytes[8].set_bit_range(0..7, ns_bits[0]);
-        bytes[9].set_bit_range(0..7, ns_bits[1]);
-    
-        push_byte_vec!(bytes, 2, 0x0);
-        let ar_bits: [u8; 2] = convert_u16_to_two_u8s!(datagram.header.additional_count, u16);
-        bytes[10].set_bit_range(0..7, ar_bits[0]);
-        bytes[11].set_bit_range(0..7, ar_bits[1]);
+    /// Write the twelve header octets, the TCP length prefix is not part of
+    /// the header and is left to the transport
+    pub fn bytes(&self, writer: &mut MessageWriter) {
+        writer.write_u16(self.id);
+        writer.write_u16(self.flags());
+        writer.write_u16(self.question_count);
+        writer.write_u16(self.answer_count);
+        writer.write_u16(self.authority_count);
+        writer.write_u16(self.additional_count);
+    }
+
+    /// Second header word with QR, OPCODE, the flag bits and RCODE
+    /// https://www.ietf.org/rfc/rfc1035.html#section-4.1.1
+    ///
+    /// Only the low four bits of the opcode and response code fit in here
+    pub fn flags(&self) -> u16 {
+        let mut flags: u16 = 0;
+
+        if self.qr == Type::Response {
+            flags |= QR;
+        }
+
+        flags |= (u8::from(self.op_code) as u16 & CODE_MASK) << OPCODE_SHIFT;
+
+        for (set, mask) in [
+            (self.authoritative, AA),
+            (self.truncated, TC),
+            (self.recursion_desired, RD),
+            (self.recursion_available, RA),
+            (self.z, Z),
+            (self.authentic_data, AD),
+            (self.checking_disabled, CD)
+        ] {
+            if set {
+                flags |= mask;
+            }
+        }
+
+        flags |= u16::from(self.error_code) & CODE_MASK;
+
+        return flags;
+    }
+
+    /// Set every field carried in the second header word, inverse of `flags`
+    pub fn set_flags(&mut self, flags: u16) {
+        self.qr = match flags & QR != 0 {
+            true => Type::Response,
+            false => Type::Query
+        };
+
+        self.op_code = OpCode::from(((flags >> OPCODE_SHIFT) & CODE_MASK) as u8);
+        self.authoritative = flags & AA != 0;
+        self.truncated = flags & TC != 0;
+        self.recursion_desired = flags & RD != 0;
+        self.recursion_available = flags & RA != 0;
+        self.z = flags & Z != 0;
+        self.authentic_data = flags & AD != 0;
+        self.checking_disabled = flags & CD != 0;
+        self.error_code = ResponseCode::from(flags & CODE_MASK);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(header: &DNSHeader) -> Vec<u8> {
+        let mut writer = MessageWriter::new();
+        header.bytes(&mut writer);
+        return writer.finish();
+    }
+
+    fn decode(bytes: &[u8], proto: TransportProto) -> Result<DNSHeader, ParseError> {
+        let mut reader = BitReader::new(bytes);
+        return DNSHeader::try_from(&mut reader, proto);
+    }
+
+    #[test]
+    fn every_flag_word_round_trips() {
+        for flags in 0..=u16::MAX {
+            let mut bytes: Vec<u8> = vec![0xBE, 0xEF];
+            bytes.extend_from_slice(&flags.to_be_bytes());
+            bytes.extend_from_slice(&[0x00, 0x80, 0x00, 0xFF, 0x80, 0x00, 0xFF, 0xFF]);
+
+            let header: DNSHeader = decode(&bytes, TransportProto::UDP).unwrap();
+
+            assert_eq!(header.id, 0xBEEF);
+            assert_eq!(header.qr == Type::Response, flags & 0x8000 != 0);
+            assert_eq!(u8::from(header.op_code) as u16, (flags >> 11) & 0xF);
+            assert_eq!(header.authoritative, flags & 0x0400 != 0);
+            assert_eq!(header.truncated, flags & 0x0200 != 0);
+            assert_eq!(header.recursion_desired, flags & 0x0100 != 0);
+            assert_eq!(header.recursion_available, flags & 0x0080 != 0);
+            assert_eq!(header.z, flags & 0x0040 != 0);
+            assert_eq!(header.authentic_data, flags & 0x0020 != 0);
+            assert_eq!(header.checking_disabled, flags & 0x0010 != 0);
+            assert_eq!(u16::from(header.error_code), flags & 0xF);
+            assert_eq!(header.question_count, 0x80);
+            assert_eq!(header.answer_count, 0xFF);
+            assert_eq!(header.authority_count, 0x8000);
+            assert_eq!(header.additional_count, 0xFFFF);
+
+            assert_eq!(header.flags(), flags);
+            assert_eq!(encode(&header), bytes);
+        }
+    }
+
+    #[test]
+    fn every_code_lands_in_its_nibble() {
+        for code in 0..=0xF_u8 {
+            let mut header = DNSHeader::new();
+            header.qr = Type::Query;
+            header.recursion_desired = false;
+            header.op_code = OpCode::from(code);
+            header.error_code = ResponseCode::from(code as u16);
+
+            let bytes: Vec<u8> = encode(&header);
+            assert_eq!(bytes[2], code << 3);
+            assert_eq!(bytes[3], code);
+
+            let decoded: DNSHeader = decode(&bytes, TransportProto::UDP).unwrap();
+            assert_eq!(decoded.op_code, header.op_code);
+            assert_eq!(decoded.error_code, header.error_code);
+        }
+    }
+
+    #[test]
+    fn tcp_length_prefix_is_read() {
+        let bytes: [u8; 14] = [0x01, 0x2C, 0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 3, 0, 4];
+        let header: DNSHeader = decode(&bytes, TransportProto::TCP).unwrap();
+
+        assert_eq!(header.length, Some(300));
+        assert_eq!(header.id, 0x1234);
+        assert_eq!(header.flags(), 0x8180);
+        assert_eq!(encode(&header), bytes[2..]);
+    }
+
+    #[test]
+    fn short_header_is_rejected() {
+        for length in 0..12 {
+            let bytes: Vec<u8> = vec![0xFF; length];
+            assert_eq!(
+                decode(&bytes, TransportProto::UDP).unwrap_err(),
+                ParseError::UnexpectedEnd
+            );
+        }
+    }
+}
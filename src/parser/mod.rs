@@ -14,6 +14,15 @@ pub mod question;
 
 /// https://www.ietf.org/rfc/rfc1035.html#section-4.1.3
 pub mod resource;
+pub mod rdata;
 
 /// https://www.rfc-editor.org/rfc/rfc1034
-pub mod fqdn;
\ No newline at end of file
+pub mod fqdn;
+
+/// https://www.ietf.org/rfc/rfc1035.html#section-4.1.4
+pub mod name;
+pub mod writer;
+pub mod error;
+
+/// https://www.rfc-editor.org/rfc/rfc6891
+pub mod edns;
\ No newline at end of file
@@ -0,0 +1,202 @@
+use bitreader::BitReader;
+use super::{
+    fqdn::{
+        FQDN,
+        MAX_NAME_LENGTH
+    },
+    error::ParseError
+};
+
+/*
+    Pointers have to jump backwards from where they are read, but a name can
+    still loop through its own labels (A -> B -> A), this limit is what ends
+    such loops and bounds the work a hostile message can make us do
+*/
+const MAX_POINTERS: usize = 64;
+
+/// Decode domain name starting at `offset` of the whole message, following
+/// compression pointers
+/// https://www.ietf.org/rfc/rfc1035.html#section-4.1.4
+///
+/// `bytes` has to start with the first octet of the header because pointers
+/// are offsets from there.
+///
+/// Returns the name and the offset right after the name at its original
+/// position (after the first pointer if there is one)
+pub fn read(bytes: &[u8], offset: usize) -> Result<(FQDN, usize), ParseError> {
+    let mut labels: Vec<Vec<u8>> = vec![];
+    let mut position: usize = offset;
+    let mut end: Option<usize> = None;
+    let mut pointers: usize = 0;
+
+    // The terminating root label counts into the total length too
+    let mut total_length: usize = 1;
+
+    loop {
+        let length: u8 = *bytes.get(position)
+            .ok_or(ParseError::UnexpectedEnd)?;
+
+        match length >> 6 {
+            0b00 => {
+                position += 1;
+
+                if length == 0 {
+                    break;
+                }
+
+                let label: &[u8] = bytes.get(position..position + length as usize)
+                    .ok_or(ParseError::UnexpectedEnd)?;
+
+                total_length += label.len() + 1;
+                if total_length > MAX_NAME_LENGTH {
+                    return Err(ParseError::NameTooLong);
+                }
+
+                labels.push(label.to_vec());
+                position += label.len();
+            },
+
+            0b11 => {
+                let second: u8 = *bytes.get(position + 1)
+                    .ok_or(ParseError::UnexpectedEnd)?;
+                let target: usize = (((length & 0x3F) as usize) << 8) | second as usize;
+
+                /*
+                    Only prior occurrences can be referenced, loops through
+                    labels read after the target are still possible and are
+                    cut off by MAX_POINTERS below
+                */
+                if target >= position {
+                    return Err(ParseError::InvalidPointer);
+                }
+
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return Err(ParseError::InvalidPointer);
+                }
+
+                if end.is_none() {
+                    end = Some(position + 2);
+                }
+
+                position = target;
+            },
+
+            // 0b01 and 0b10 are extended and reserved label types
+            _ => {
+                return Err(ParseError::InvalidLabelType);
+            }
+        }
+    }
+
+    Ok((FQDN::from_labels(labels)?, end.unwrap_or(position)))
+}
+
+/// Decode domain name at the current reader position and advance the reader
+/// past it
+///
+/// `bytes` is the whole message the reader was created from
+pub fn read_from(reader: &mut BitReader, bytes: &[u8]) -> Result<FQDN, ParseError> {
+    let start: usize = (reader.position() / 8) as usize;
+    let (name, end) = read(bytes, start)?;
+
+    reader.skip((end - start) as u64 * 8)?;
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pointers count from the start of the message so names sit behind a header
+    const HEADER: [u8; 12] = [0; 12];
+
+    fn message(name: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = HEADER.to_vec();
+        bytes.extend_from_slice(name);
+        return bytes;
+    }
+
+    fn label(length: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![length as u8];
+        bytes.resize(length + 1, b'a');
+        return bytes;
+    }
+
+    #[test]
+    fn pointer_is_followed_and_end_is_after_it() {
+        // example.com at 12, www + pointer to it at 25
+        let bytes: Vec<u8> = message(b"\x07example\x03com\x00\x03www\xC0\x0C");
+
+        let (name, end) = read(&bytes, 25).unwrap();
+
+        assert_eq!(name.to_string(), "www.example.com");
+        assert_eq!(end, bytes.len());
+    }
+
+    #[test]
+    fn pointer_loop_through_labels_is_rejected() {
+        // Label "a" at 12 followed by a pointer back to it at 14
+        let bytes: Vec<u8> = message(b"\x01a\xC0\x0C");
+
+        assert_eq!(read(&bytes, 12).unwrap_err(), ParseError::InvalidPointer);
+    }
+
+    #[test]
+    fn pointer_to_itself_or_forward_is_rejected() {
+        let to_itself: Vec<u8> = message(b"\xC0\x0C");
+        let forward: Vec<u8> = message(b"\xC0\x0E\x01a\x00");
+
+        assert_eq!(read(&to_itself, 12).unwrap_err(), ParseError::InvalidPointer);
+        assert_eq!(read(&forward, 12).unwrap_err(), ParseError::InvalidPointer);
+    }
+
+    #[test]
+    fn name_of_255_octets_is_accepted_and_longer_rejected() {
+        let mut longest: Vec<u8> = [label(63), label(63), label(63), label(61)].concat();
+        longest.push(0);
+
+        let mut too_long: Vec<u8> = [label(63), label(63), label(63), label(62)].concat();
+        too_long.push(0);
+
+        let (name, _) = read(&message(&longest), 12).unwrap();
+
+        assert_eq!(name.len(), MAX_NAME_LENGTH);
+        assert_eq!(read(&message(&too_long), 12).unwrap_err(), ParseError::NameTooLong);
+    }
+
+    #[test]
+    fn name_too_long_through_pointers_is_rejected() {
+        // 200 octets at 12 and another 60 in front of a pointer to them
+        let mut bytes: Vec<u8> = message(&[label(63), label(63), label(63), label(7)].concat());
+        bytes.push(0);
+
+        let start: usize = bytes.len();
+        bytes.extend_from_slice(&label(59));
+        bytes.extend_from_slice(&[0xC0, 0x0C]);
+
+        assert_eq!(read(&bytes, start).unwrap_err(), ParseError::NameTooLong);
+    }
+
+    #[test]
+    fn label_over_63_octets_is_rejected() {
+        // Length 64 has the upper bits 0b01 and reads as an extended label type
+        let mut bytes: Vec<u8> = label(64);
+        bytes.push(0);
+
+        assert_eq!(read(&message(&bytes), 12).unwrap_err(), ParseError::InvalidLabelType);
+    }
+
+    #[test]
+    fn read_from_advances_past_first_pointer() {
+        let bytes: Vec<u8> = message(b"\x03com\x00\xC0\x0C\xFF");
+        let mut reader = BitReader::new(&bytes);
+        reader.skip(17 * 8).unwrap();
+
+        let name: FQDN = read_from(&mut reader, &bytes).unwrap();
+
+        assert_eq!(name.to_string(), "com");
+        assert_eq!(reader.read_u8(8).unwrap(), 0xFF);
+    }
+}
@@ -1,22 +1,43 @@
-enum_from_primitive! {
-    #[derive(Debug, PartialEq, Clone, Copy)]
-    pub enum OpCode {
-        Query = 0x0,
-        IQuery = 0x1,
-        Status = 0x2
-    }
+/// Kind of query, four bits of the header. Values without a definition here
+/// are kept so the header can be written back unchanged
+/// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-5
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OpCode {
+    Query,
+    IQuery,
+    Status,
+    Notify,
+    Update,
+    DSO,
+
+    /// Unassigned opcode, only the low four bits are ever written
+    Unknown(u8)
 }
 
-impl TryInto<u8> for OpCode {
-    type Error = String;
+impl From<u8> for OpCode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x0 => OpCode::Query,
+            0x1 => OpCode::IQuery,
+            0x2 => OpCode::Status,
+            0x4 => OpCode::Notify,
+            0x5 => OpCode::Update,
+            0x6 => OpCode::DSO,
+            other => OpCode::Unknown(other)
+        }
+    }
+}
 
-    fn try_into(self) -> Result<u8, Self::Error> {
-        let res = match self {
+impl From<OpCode> for u8 {
+    fn from(value: OpCode) -> Self {
+        match value {
             OpCode::Query => 0x0,
             OpCode::IQuery => 0x1,
-            OpCode::Status => 0x2
-        };
-
-        Ok(res)
+            OpCode::Status => 0x2,
+            OpCode::Notify => 0x4,
+            OpCode::Update => 0x5,
+            OpCode::DSO => 0x6,
+            OpCode::Unknown(other) => other
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,10 +1,59 @@
-enum_from_primitive! {
-    #[repr(u16)]
-    #[derive(Debug, Clone, Copy)]
-    pub enum QuestionClass {
-        IN = 0x1,
-        CS = 0x2,
-        CH = 0x3,
-        HS = 0x4
+use std::fmt;
+
+/// Classes are an open set just like types, unknown ones keep their number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuestionClass {
+    IN,
+    CS,
+    CH,
+    HS,
+    NONE,
+    ANY,
+
+    /// Class without a mnemonic https://www.rfc-editor.org/rfc/rfc3597
+    Unknown(u16)
+}
+
+impl From<u16> for QuestionClass {
+    fn from(value: u16) -> Self {
+        match value {
+            0x1 => QuestionClass::IN,
+            0x2 => QuestionClass::CS,
+            0x3 => QuestionClass::CH,
+            0x4 => QuestionClass::HS,
+            0xFE => QuestionClass::NONE,
+            0xFF => QuestionClass::ANY,
+            other => QuestionClass::Unknown(other)
+        }
     }
-}
\ No newline at end of file
+}
+
+impl From<QuestionClass> for u16 {
+    fn from(value: QuestionClass) -> Self {
+        match value {
+            QuestionClass::IN => 0x1,
+            QuestionClass::CS => 0x2,
+            QuestionClass::CH => 0x3,
+            QuestionClass::HS => 0x4,
+            QuestionClass::NONE => 0xFE,
+            QuestionClass::ANY => 0xFF,
+            QuestionClass::Unknown(other) => other
+        }
+    }
+}
+
+/// Mnemonic of the class, or CLASS<number> for unknown ones
+/// https://www.rfc-editor.org/rfc/rfc3597#section-5
+impl fmt::Display for QuestionClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuestionClass::IN => write!(f, "IN"),
+            QuestionClass::CS => write!(f, "CS"),
+            QuestionClass::CH => write!(f, "CH"),
+            QuestionClass::HS => write!(f, "HS"),
+            QuestionClass::NONE => write!(f, "NONE"),
+            QuestionClass::ANY => write!(f, "ANY"),
+            QuestionClass::Unknown(other) => write!(f, "CLASS{}", other)
+        }
+    }
+}
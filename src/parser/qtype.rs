@@ -1,84 +1,133 @@
-enum_from_primitive! {
-    #[repr(u16)]
-    #[derive(Debug, Clone, Copy)]
-    pub enum QuestionType {
-        A = 1,
-        NS = 2,
-        MD = 3,
-        MF = 4,
-        CNAME = 5,
-        SOA = 6,
-        MB = 7,
-        MG = 8,
-        MR = 9, 
-        NULL = 10,
-        WKS = 11,
-        PTR = 12,
-        HINFO = 13,
-        MINFO = 14,
-        MX = 15,
-        TXT = 16,
-        RP = 17,
-        AFSDB = 18,
-        X25 = 19,
-        ISDN = 20,
-        RT = 21,
-        NSAP = 22,
-        SIG = 24,
-        KEY = 25,
-        PX = 26,
-        GPOS = 27,
-        AAAA = 28,
-        LOC = 29,
-        NXT = 30,
-        EID = 31,
-        NB = 32,
-        NBSTAT = 33,
-        ATMA = 34,
-        NAPTR = 35,
-        KX = 36,
-        CERT = 37,
-        DNAME = 39,
-        OPTION = 41,
-        APL = 42,
-        DS = 43,
-        SSHFP = 44,
-        IPSECKEY = 45,
-        RRSIG = 46,
-        NSEC = 47,
-        DNSKEY = 48,
-        DHCID = 49,
-        NSEC3 = 50,
-        NSEC3PARAM = 51,
-        TLSA = 52,
-        SMIMEA = 53,
-        HIP = 55,
-        NINFO = 56,
-        RKEY = 57,
-        TALINK = 58,
-        CDS = 59,
-        CDNSKEY = 60,
-        OPENPGPKEY = 61,
-        CSYNC = 62,
-        ZONEMD = 63,
-        SVCB = 64,
-        HTTPS = 65,
-        SPF = 99,
-        UINFO = 100,
-        UID = 101,
-        GID = 102,
-        UNSPEC = 103,
-        NID = 104,
-        L32 = 105,
-        L64 = 106,
-        LP = 107,
-        EUI48 = 108,
-        EUI64 = 109,
-        TKEY = 249,
-        TSIG = 250,
-        IXFR = 251,
-        AXFR = 252,
-        MAILB = 253,
-        MAILA = 254,
-    }
-}
\ No newline at end of file
+use std::fmt;
+
+/*
+    Record types are not a closed set, anything without a mnemonic here is
+    still a valid type, so instead of enum_from_primitive the enum is
+    generated with a variant carrying the number
+*/
+macro_rules! question_types {
+    ($($name:ident = $value:expr),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum QuestionType {
+            $($name,)*
+
+            /// Type without a mnemonic https://www.rfc-editor.org/rfc/rfc3597
+            ///
+            /// Always construct it through `QuestionType::from`, so known
+            /// numbers are never wrapped in it
+            Unknown(u16)
+        }
+
+        impl From<u16> for QuestionType {
+            fn from(value: u16) -> Self {
+                match value {
+                    $($value => QuestionType::$name,)*
+                    other => QuestionType::Unknown(other)
+                }
+            }
+        }
+
+        impl From<QuestionType> for u16 {
+            fn from(value: QuestionType) -> Self {
+                match value {
+                    $(QuestionType::$name => $value,)*
+                    QuestionType::Unknown(other) => other
+                }
+            }
+        }
+
+        /// Mnemonic of the type, or TYPE<number> for unknown ones
+        /// https://www.rfc-editor.org/rfc/rfc3597#section-5
+        impl fmt::Display for QuestionType {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(QuestionType::$name => write!(f, stringify!($name)),)*
+                    QuestionType::Unknown(other) => write!(f, "TYPE{}", other)
+                }
+            }
+        }
+    };
+}
+
+question_types! {
+    A = 1,
+    NS = 2,
+    MD = 3,
+    MF = 4,
+    CNAME = 5,
+    SOA = 6,
+    MB = 7,
+    MG = 8,
+    MR = 9,
+    NULL = 10,
+    WKS = 11,
+    PTR = 12,
+    HINFO = 13,
+    MINFO = 14,
+    MX = 15,
+    TXT = 16,
+    RP = 17,
+    AFSDB = 18,
+    X25 = 19,
+    ISDN = 20,
+    RT = 21,
+    NSAP = 22,
+    SIG = 24,
+    KEY = 25,
+    PX = 26,
+    GPOS = 27,
+    AAAA = 28,
+    LOC = 29,
+    NXT = 30,
+    EID = 31,
+    NIMLOC = 32,
+    SRV = 33,
+    ATMA = 34,
+    NAPTR = 35,
+    KX = 36,
+    CERT = 37,
+    DNAME = 39,
+    OPTION = 41,
+    APL = 42,
+    DS = 43,
+    SSHFP = 44,
+    IPSECKEY = 45,
+    RRSIG = 46,
+    NSEC = 47,
+    DNSKEY = 48,
+    DHCID = 49,
+    NSEC3 = 50,
+    NSEC3PARAM = 51,
+    TLSA = 52,
+    SMIMEA = 53,
+    HIP = 55,
+    NINFO = 56,
+    RKEY = 57,
+    TALINK = 58,
+    CDS = 59,
+    CDNSKEY = 60,
+    OPENPGPKEY = 61,
+    CSYNC = 62,
+    ZONEMD = 63,
+    SVCB = 64,
+    HTTPS = 65,
+    SPF = 99,
+    UINFO = 100,
+    UID = 101,
+    GID = 102,
+    UNSPEC = 103,
+    NID = 104,
+    L32 = 105,
+    L64 = 106,
+    LP = 107,
+    EUI48 = 108,
+    EUI64 = 109,
+    TKEY = 249,
+    TSIG = 250,
+    IXFR = 251,
+    AXFR = 252,
+    MAILB = 253,
+    MAILA = 254,
+    ANY = 255,
+    CAA = 257
+}
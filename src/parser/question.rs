@@ -1,15 +1,11 @@
 use bitreader::BitReader;
-use enum_primitive::FromPrimitive;
-use crate::{
-    convert_u16_to_two_u8s, 
-    push_byte_vec
-};
-
 use super::{
     qclass::QuestionClass,
     qtype::QuestionType,
-    rcode::ResponseCode, dns::DNS, 
-    fqdn::FQDN
+    error::ParseError, dns::DNS, 
+    fqdn::FQDN,
+    name,
+    writer::MessageWriter
 };
 
 #[derive(Debug, Clone)]
@@ -20,7 +16,9 @@ pub struct DNSQuestion {
 }
 
 impl DNSQuestion {
-    pub fn try_from(reader: &mut BitReader, count: u16) -> Result<Vec<Self>, ResponseCode> {
+    /// Parse `count` questions starting at the current reader position, `bytes`
+    /// is the whole message the reader was created from
+    pub fn try_from(reader: &mut BitReader, bytes: &[u8], count: u16) -> Result<Vec<Self>, ParseError> {
         let mut questions: Vec<Self> = vec![];
         for _ in 0..count {
             let mut question: DNSQuestion = DNSQuestion { 
@@ -29,43 +27,10 @@ impl DNSQuestion {
                 class: QuestionClass::CH
             };
 
-            let mut qname: String = String::new();
-
-            loop {
-                let bytes_to_read: u8 = reader.read_u8(8).unwrap();
-
-                // Separating byte will end the loop
-                if bytes_to_read == 0 {
-                    break;
-                } 
-
-                if qname.len() != 0 {
-                    qname.push('.');
-                }
-
-                for _ in 0..bytes_to_read {
-                    qname.push(std::char::from_u32(
-                        reader.read_u32(8)
-                            .unwrap()
-                        )
-                        .unwrap()
-                    );
-                }
-            }
-
-            let name_res: Result<FQDN, ResponseCode> = FQDN::try_from(qname);
-            let qtype_opt: Option<QuestionType> = QuestionType::from_u16(reader.read_u16(16).unwrap());
-            let qclass_opt: Option<QuestionClass> = QuestionClass::from_u16(reader.read_u16(16).unwrap());
-
-            if name_res.is_err() || qtype_opt.is_none() || qclass_opt.is_none() {
-                return Result::Err::<Vec<Self>, ResponseCode>(
-                    ResponseCode::FormatError
-                );
-            } else {
-                question.name = name_res.unwrap();
-                question.class = qclass_opt.unwrap();
-                question.qtype = qtype_opt.unwrap();
-            };
+            // Any type and class number is valid, unknown ones are carried as is
+            question.name = name::read_from(reader, bytes)?;
+            question.qtype = QuestionType::from(reader.read_u16(16)?);
+            question.class = QuestionClass::from(reader.read_u16(16)?);
 
             questions.push(question);
         };
@@ -73,51 +38,15 @@ impl DNSQuestion {
         Ok(questions)
     }
 
-    pub fn bytes(bytes: &mut Vec<u8>, datagram: &DNS) {
-        let mut offset: u128 = 12;
-
+    pub fn bytes(writer: &mut MessageWriter, datagram: &DNS) {
         if datagram.questions.is_none() {
             return;
         };
 
         for question in datagram.questions.as_ref().unwrap() {
-            let name_parts = question.name.split();
-
-            let complete_length = question.name.len() + 6;
-            push_byte_vec!(bytes, complete_length as u8 + 1, 0x0);
-
-            // First write the initial length byte and then the content
-            for name in name_parts {
-                let length: usize = name.len();
-
-                bytes[offset as usize] = length as u8;
-                offset += 1;
-
-                name.as_bytes()
-                    .into_iter()
-                    .for_each(|byte: &u8| {
-                        bytes[offset as usize] = *byte; 
-                        offset += 1;
-                    });
-            }
-
-            // Terminating octet to make it clean that this is the end of domain name
-            bytes[offset as usize] = 0x00; 
-            offset += 1;
-
-            let qtype_bytes: [u8; 2] = convert_u16_to_two_u8s!(question.qtype as u16, u16);
-
-            bytes[offset as usize] = qtype_bytes[0];
-            offset += 1;
-            bytes[offset as usize] = qtype_bytes[1];
-            offset += 1;
-
-            let qclass_bytes: [u8; 2] = convert_u16_to_two_u8s!(question.class as u16, u16);
-
-            bytes[offset as usize] = qclass_bytes[0];
-            offset += 1;
-            bytes[offset as usize] = qclass_bytes[1];
-            offset += 1;
+            writer.write_name(question.name.labels(), true);
+            writer.write_u16(u16::from(question.qtype));
+            writer.write_u16(u16::from(question.class));
         };
     }
-}
\ No newline at end of file
+}
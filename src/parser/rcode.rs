@@ -1,28 +1,63 @@
-enum_from_primitive! {
-    #[derive(Debug, PartialEq, Clone, Copy)]
-    pub enum ResponseCode {
-        NoError = 0x0,
-        FormatError = 0x1,
-        ServerFailure = 0x2,
-        NameError = 0x3,
-        NotImplemented = 0x4,
-        Refused = 0x5
-    }
+/// Response code, the header carries the low four bits of it
+/// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-6
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ResponseCode {
+    NoError,
+    FormatError,
+    ServerFailure,
+    NameError,
+    NotImplemented,
+    Refused,
+    YXDomain,
+    YXRRSet,
+    NXRRSet,
+    NotAuth,
+    NotZone,
+
+    /// Only expressible with the extended response code of EDNS
+    /// https://www.rfc-editor.org/rfc/rfc6891#section-9
+    BadVersion,
+
+    /// Unassigned or reserved code, kept so it can be written back
+    Unknown(u16)
 }
 
-impl TryInto<u8> for ResponseCode {
-    type Error = String; 
+impl From<u16> for ResponseCode {
+    fn from(value: u16) -> Self {
+        match value {
+            0x0 => ResponseCode::NoError,
+            0x1 => ResponseCode::FormatError,
+            0x2 => ResponseCode::ServerFailure,
+            0x3 => ResponseCode::NameError,
+            0x4 => ResponseCode::NotImplemented,
+            0x5 => ResponseCode::Refused,
+            0x6 => ResponseCode::YXDomain,
+            0x7 => ResponseCode::YXRRSet,
+            0x8 => ResponseCode::NXRRSet,
+            0x9 => ResponseCode::NotAuth,
+            0xA => ResponseCode::NotZone,
+            0x10 => ResponseCode::BadVersion,
+            other => ResponseCode::Unknown(other)
+        }
+    }
+}
 
-    fn try_into(self) -> Result<u8, Self::Error> {
-        let res = match self {
+impl From<ResponseCode> for u16 {
+    fn from(value: ResponseCode) -> Self {
+        match value {
             ResponseCode::NoError => 0x0,
             ResponseCode::FormatError => 0x1,
             ResponseCode::ServerFailure => 0x2,
             ResponseCode::NameError => 0x3,
             ResponseCode::NotImplemented => 0x4,
-            ResponseCode::Refused => 0x5
-        };
-
-        Ok(res)
+            ResponseCode::Refused => 0x5,
+            ResponseCode::YXDomain => 0x6,
+            ResponseCode::YXRRSet => 0x7,
+            ResponseCode::NXRRSet => 0x8,
+            ResponseCode::NotAuth => 0x9,
+            ResponseCode::NotZone => 0xA,
+            ResponseCode::BadVersion => 0x10,
+            ResponseCode::Unknown(other) => other
+        }
     }
-}
\ No newline at end of file
+}
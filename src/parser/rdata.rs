@@ -0,0 +1,1685 @@
+use std::fmt;
+use std::net::{
+    Ipv4Addr,
+    Ipv6Addr
+};
+use super::{
+    edns::EdnsOption,
+    error::ParseError,
+    fqdn::FQDN,
+    name,
+    qtype::QuestionType,
+    rcode::ResponseCode,
+    writer::MessageWriter
+};
+
+/// Typed RDATA of a resource record, every variant knows how to read itself
+/// from the wire and write itself back.
+///
+/// Character strings and binary fields are kept as raw bytes. Anything without a known layout is kept as
+/// `Unknown` raw bytes, see https://www.rfc-editor.org/rfc/rfc3597
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    /// https://www.ietf.org/rfc/rfc1035.html#section-3.4.1
+    A(Ipv4Addr),
+    NS(FQDN),
+    MD(FQDN),
+    MF(FQDN),
+    CNAME(FQDN),
+    SOA(StartOfAuthority),
+    MB(FQDN),
+    MG(FQDN),
+    MR(FQDN),
+    NULL(Vec<u8>),
+    WKS {
+        address: Ipv4Addr,
+        protocol: u8,
+        bitmap: Vec<u8>
+    },
+    PTR(FQDN),
+    HINFO {
+        cpu: Vec<u8>,
+        os: Vec<u8>
+    },
+    MINFO {
+        responsible_mailbox: FQDN,
+        error_mailbox: FQDN
+    },
+    MX {
+        preference: u16,
+        exchange: FQDN
+    },
+    TXT(Vec<Vec<u8>>),
+
+    /// https://www.rfc-editor.org/rfc/rfc1183
+    RP {
+        mailbox: FQDN,
+        txt: FQDN
+    },
+    AFSDB {
+        subtype: u16,
+        hostname: FQDN
+    },
+    X25(Vec<u8>),
+    ISDN {
+        address: Vec<u8>,
+        subaddress: Option<Vec<u8>>
+    },
+    RT {
+        preference: u16,
+        intermediate: FQDN
+    },
+    NSAP(Vec<u8>),
+    SIG(Signature),
+    KEY(PublicKey),
+    PX {
+        preference: u16,
+        map822: FQDN,
+        mapx400: FQDN
+    },
+    GPOS {
+        longitude: Vec<u8>,
+        latitude: Vec<u8>,
+        altitude: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc3596
+    AAAA(Ipv6Addr),
+
+    /// https://www.rfc-editor.org/rfc/rfc1876
+    LOC {
+        version: u8,
+        size: u8,
+        horizontal_precision: u8,
+        vertical_precision: u8,
+        latitude: u32,
+        longitude: u32,
+        altitude: u32
+    },
+    NXT {
+        next: FQDN,
+        bitmap: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc2782
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: FQDN
+    },
+    ATMA {
+        format: u8,
+        address: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc3403
+    NAPTR {
+        order: u16,
+        preference: u16,
+        flags: Vec<u8>,
+        services: Vec<u8>,
+        regexp: Vec<u8>,
+        replacement: FQDN
+    },
+    KX {
+        preference: u16,
+        exchanger: FQDN
+    },
+    CERT {
+        cert_type: u16,
+        key_tag: u16,
+        algorithm: u8,
+        certificate: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc6672
+    DNAME(FQDN),
+
+    /// Options of the EDNS pseudo record
+    /// https://www.rfc-editor.org/rfc/rfc6891#section-6.1.2
+    OPT(Vec<EdnsOption>),
+    APL(Vec<AddressPrefix>),
+
+    /// https://www.rfc-editor.org/rfc/rfc4034
+    DS(Digest),
+    SSHFP {
+        algorithm: u8,
+        fingerprint_type: u8,
+        fingerprint: Vec<u8>
+    },
+    IPSECKEY {
+        precedence: u8,
+        algorithm: u8,
+        gateway: Gateway,
+        public_key: Vec<u8>
+    },
+    RRSIG(Signature),
+    NSEC {
+        next: FQDN,
+        types: Vec<u16>
+    },
+    DNSKEY(PublicKey),
+    DHCID(Vec<u8>),
+
+    /// https://www.rfc-editor.org/rfc/rfc5155
+    NSEC3 {
+        algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+        next_hashed: Vec<u8>,
+        types: Vec<u16>
+    },
+    NSEC3PARAM {
+        algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc6698
+    TLSA(Association),
+    SMIMEA(Association),
+    HIP {
+        algorithm: u8,
+        hit: Vec<u8>,
+        public_key: Vec<u8>,
+        servers: Vec<FQDN>
+    },
+    NINFO(Vec<Vec<u8>>),
+    RKEY(PublicKey),
+    TALINK {
+        previous: FQDN,
+        next: FQDN
+    },
+    CDS(Digest),
+    CDNSKEY(PublicKey),
+    OPENPGPKEY(Vec<u8>),
+    CSYNC {
+        serial: u32,
+        flags: u16,
+        types: Vec<u16>
+    },
+    ZONEMD {
+        serial: u32,
+        scheme: u8,
+        algorithm: u8,
+        digest: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc9460
+    SVCB(ServiceBinding),
+    HTTPS(ServiceBinding),
+    SPF(Vec<Vec<u8>>),
+    UINFO(Vec<u8>),
+    UID(u32),
+    GID(u32),
+    UNSPEC(Vec<u8>),
+
+    /// https://www.rfc-editor.org/rfc/rfc6742
+    NID {
+        preference: u16,
+        node_id: u64
+    },
+    L32 {
+        preference: u16,
+        locator: Ipv4Addr
+    },
+    L64 {
+        preference: u16,
+        locator: u64
+    },
+    LP {
+        preference: u16,
+        fqdn: FQDN
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc7043
+    EUI48([u8; 6]),
+    EUI64([u8; 8]),
+
+    /// https://www.rfc-editor.org/rfc/rfc2930
+    TKEY {
+        algorithm: FQDN,
+        inception: u32,
+        expiration: u32,
+        mode: u16,
+        error: u16,
+        key: Vec<u8>,
+        other: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc8945
+    TSIG {
+        algorithm: FQDN,
+        time_signed: u64,
+        fudge: u16,
+        mac: Vec<u8>,
+        original_id: u16,
+        error: u16,
+        other: Vec<u8>
+    },
+
+    /// https://www.rfc-editor.org/rfc/rfc8659
+    CAA {
+        flags: u8,
+        tag: Vec<u8>,
+        value: Vec<u8>
+    },
+
+    /// RDATA of types without a known layout, kept byte for byte
+    Unknown(Vec<u8>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartOfAuthority {
+    pub mname: FQDN,
+    pub rname: FQDN,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32
+}
+
+/// RRSIG and the older SIG record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub type_covered: u16,
+    pub algorithm: u8,
+    pub labels: u8,
+    pub original_ttl: u32,
+    pub expiration: u32,
+    pub inception: u32,
+    pub key_tag: u16,
+    pub signer: FQDN,
+    pub signature: Vec<u8>
+}
+
+/// DNSKEY, CDNSKEY, RKEY and the older KEY record
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicKey {
+    pub flags: u16,
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub public_key: Vec<u8>
+}
+
+/// DS and CDS records
+#[derive(Debug, Clone, PartialEq)]
+pub struct Digest {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: Vec<u8>
+}
+
+/// TLSA and SMIMEA records
+#[derive(Debug, Clone, PartialEq)]
+pub struct Association {
+    pub usage: u8,
+    pub selector: u8,
+    pub matching_type: u8,
+    pub data: Vec<u8>
+}
+
+/// SVCB and HTTPS records, parameter values are kept raw
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceBinding {
+    pub priority: u16,
+    pub target: FQDN,
+    pub params: Vec<(u16, Vec<u8>)>
+}
+
+/// One item of the APL record https://www.rfc-editor.org/rfc/rfc3123
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressPrefix {
+    pub family: u16,
+    pub prefix: u8,
+    pub negation: bool,
+    pub address: Vec<u8>
+}
+
+/// Gateway of the IPSECKEY record, the variant decides the gateway type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gateway {
+    None,
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+    Name(FQDN)
+}
+
+/// Reads fields of a single RDATA section, never past its end
+struct RDataReader<'a> {
+    /// Whole message, names in RDATA may point anywhere before them
+    bytes: &'a [u8],
+    position: usize,
+    end: usize,
+
+    /// Whether names of this type may be compressed at all
+    compression: bool,
+
+    /// Octets the names would take on top of RDLENGTH without pointers
+    expanded: usize
+}
+
+impl<'a> RDataReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ParseError> {
+        if self.position + count > self.end {
+            return Err(ParseError::RDataLength);
+        }
+
+        let slice: &'a [u8] = &self.bytes[self.position..self.position + count];
+        self.position += count;
+
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseError> {
+        let bytes: &[u8] = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let bytes: &[u8] = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, ParseError> {
+        Ok(((self.u32()? as u64) << 32) | self.u32()? as u64)
+    }
+
+    fn ipv4(&mut self) -> Result<Ipv4Addr, ParseError> {
+        let octets: [u8; 4] = self.take(4)?.try_into()
+            .map_err(|_| ParseError::RDataLength)?;
+
+        Ok(Ipv4Addr::from(octets))
+    }
+
+    fn ipv6(&mut self) -> Result<Ipv6Addr, ParseError> {
+        let octets: [u8; 16] = self.take(16)?.try_into()
+            .map_err(|_| ParseError::RDataLength)?;
+
+        Ok(Ipv6Addr::from(octets))
+    }
+
+    /// Everything left in RDATA
+    fn rest(&mut self) -> Result<Vec<u8>, ParseError> {
+        Ok(self.take(self.end - self.position)?.to_vec())
+    }
+
+    fn is_done(&self) -> bool {
+        self.position == self.end
+    }
+
+    /// <character-string> is a single length octet followed by that many octets
+    fn string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let length: usize = self.u8()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    fn strings(&mut self) -> Result<Vec<Vec<u8>>, ParseError> {
+        let mut strings: Vec<Vec<u8>> = vec![];
+
+        while !self.is_done() {
+            strings.push(self.string()?);
+        }
+
+        Ok(strings)
+    }
+
+    /// Compressed names are only accepted in types that allow them, the name
+    /// itself still has to end within RDATA
+    fn name(&mut self) -> Result<FQDN, ParseError> {
+        let (name, next) = name::read(self.bytes, self.position)?;
+
+        if next > self.end {
+            return Err(ParseError::RDataLength);
+        }
+
+        // Without a pointer the name takes exactly its own length on the wire
+        let wire_length: usize = next - self.position;
+        if wire_length != name.len() {
+            if !self.compression {
+                return Err(ParseError::InvalidPointer);
+            }
+
+            self.expanded += name.len().saturating_sub(wire_length);
+        }
+
+        self.position = next;
+        Ok(name)
+    }
+
+    /// Type bitmap used by NSEC, NSEC3 and CSYNC
+    /// https://www.rfc-editor.org/rfc/rfc4034#section-4.1.2
+    fn types(&mut self) -> Result<Vec<u16>, ParseError> {
+        let mut types: Vec<u16> = vec![];
+
+        while !self.is_done() {
+            let window: u8 = self.u8()?;
+            let length: usize = self.u8()? as usize;
+
+            if length == 0 || length > 32 {
+                return Err(ParseError::InvalidValue);
+            }
+
+            for (i, byte) in self.take(length)?.iter().enumerate() {
+                for bit in 0..8 {
+                    if byte & (0x80 >> bit) != 0 {
+                        types.push(((window as u16) << 8) | (i * 8 + bit) as u16);
+                    }
+                }
+            }
+        }
+
+        Ok(types)
+    }
+}
+
+impl RData {
+    /// Parse RDATA of `rr_type` that spans `length` bytes from `start` of the
+    /// whole message
+    pub fn read(rr_type: QuestionType, bytes: &[u8], start: usize, length: usize) -> Result<RData, ParseError> {
+        let mut r = RDataReader {
+            bytes,
+            position: start,
+            end: start + length,
+            compression: allows_compression(rr_type),
+            expanded: 0
+        };
+
+        if r.end > bytes.len() {
+            return Err(ParseError::UnexpectedEnd);
+        }
+
+        let data: RData = match rr_type {
+            QuestionType::A => RData::A(r.ipv4()?),
+            QuestionType::NS => RData::NS(r.name()?),
+            QuestionType::MD => RData::MD(r.name()?),
+            QuestionType::MF => RData::MF(r.name()?),
+            QuestionType::CNAME => RData::CNAME(r.name()?),
+            QuestionType::SOA => RData::SOA(StartOfAuthority {
+                mname: r.name()?,
+                rname: r.name()?,
+                serial: r.u32()?,
+                refresh: r.u32()?,
+                retry: r.u32()?,
+                expire: r.u32()?,
+                minimum: r.u32()?
+            }),
+            QuestionType::MB => RData::MB(r.name()?),
+            QuestionType::MG => RData::MG(r.name()?),
+            QuestionType::MR => RData::MR(r.name()?),
+            QuestionType::NULL => RData::NULL(r.rest()?),
+            QuestionType::WKS => RData::WKS {
+                address: r.ipv4()?,
+                protocol: r.u8()?,
+                bitmap: r.rest()?
+            },
+            QuestionType::PTR => RData::PTR(r.name()?),
+            QuestionType::HINFO => RData::HINFO {
+                cpu: r.string()?,
+                os: r.string()?
+            },
+            QuestionType::MINFO => RData::MINFO {
+                responsible_mailbox: r.name()?,
+                error_mailbox: r.name()?
+            },
+            QuestionType::MX => RData::MX {
+                preference: r.u16()?,
+                exchange: r.name()?
+            },
+            QuestionType::TXT => RData::TXT(r.strings()?),
+            QuestionType::RP => RData::RP {
+                mailbox: r.name()?,
+                txt: r.name()?
+            },
+            QuestionType::AFSDB => RData::AFSDB {
+                subtype: r.u16()?,
+                hostname: r.name()?
+            },
+            QuestionType::X25 => RData::X25(r.string()?),
+            QuestionType::ISDN => RData::ISDN {
+                address: r.string()?,
+                subaddress: match r.is_done() {
+                    true => None,
+                    false => Some(r.string()?)
+                }
+            },
+            QuestionType::RT => RData::RT {
+                preference: r.u16()?,
+                intermediate: r.name()?
+            },
+            QuestionType::NSAP => RData::NSAP(r.rest()?),
+            QuestionType::SIG => RData::SIG(Signature::read(&mut r)?),
+            QuestionType::KEY => RData::KEY(PublicKey::read(&mut r)?),
+            QuestionType::PX => RData::PX {
+                preference: r.u16()?,
+                map822: r.name()?,
+                mapx400: r.name()?
+            },
+            QuestionType::GPOS => RData::GPOS {
+                longitude: r.string()?,
+                latitude: r.string()?,
+                altitude: r.string()?
+            },
+            QuestionType::AAAA => RData::AAAA(r.ipv6()?),
+            QuestionType::LOC => RData::LOC {
+                version: r.u8()?,
+                size: r.u8()?,
+                horizontal_precision: r.u8()?,
+                vertical_precision: r.u8()?,
+                latitude: r.u32()?,
+                longitude: r.u32()?,
+                altitude: r.u32()?
+            },
+            QuestionType::NXT => RData::NXT {
+                next: r.name()?,
+                bitmap: r.rest()?
+            },
+            QuestionType::SRV => RData::SRV {
+                priority: r.u16()?,
+                weight: r.u16()?,
+                port: r.u16()?,
+                target: r.name()?
+            },
+            QuestionType::ATMA => RData::ATMA {
+                format: r.u8()?,
+                address: r.rest()?
+            },
+            QuestionType::NAPTR => RData::NAPTR {
+                order: r.u16()?,
+                preference: r.u16()?,
+                flags: r.string()?,
+                services: r.string()?,
+                regexp: r.string()?,
+                replacement: r.name()?
+            },
+            QuestionType::KX => RData::KX {
+                preference: r.u16()?,
+                exchanger: r.name()?
+            },
+            QuestionType::CERT => RData::CERT {
+                cert_type: r.u16()?,
+                key_tag: r.u16()?,
+                algorithm: r.u8()?,
+                certificate: r.rest()?
+            },
+            QuestionType::DNAME => RData::DNAME(r.name()?),
+            QuestionType::OPTION => {
+                let mut options: Vec<EdnsOption> = vec![];
+
+                while !r.is_done() {
+                    let code: u16 = r.u16()?;
+                    let length: u16 = r.u16()?;
+
+                    options.push(EdnsOption {
+                        code,
+                        data: r.take(length as usize)?.to_vec()
+                    });
+                }
+
+                RData::OPT(options)
+            },
+            QuestionType::APL => {
+                let mut prefixes: Vec<AddressPrefix> = vec![];
+
+                while !r.is_done() {
+                    let family: u16 = r.u16()?;
+                    let prefix: u8 = r.u8()?;
+                    let length: u8 = r.u8()?;
+
+                    prefixes.push(AddressPrefix {
+                        family,
+                        prefix,
+                        negation: length & 0x80 != 0,
+                        address: r.take((length & 0x7F) as usize)?.to_vec()
+                    });
+                }
+
+                RData::APL(prefixes)
+            },
+            QuestionType::DS => RData::DS(Digest::read(&mut r)?),
+            QuestionType::SSHFP => RData::SSHFP {
+                algorithm: r.u8()?,
+                fingerprint_type: r.u8()?,
+                fingerprint: r.rest()?
+            },
+            QuestionType::IPSECKEY => {
+                let precedence: u8 = r.u8()?;
+                let gateway_type: u8 = r.u8()?;
+                let algorithm: u8 = r.u8()?;
+
+                let gateway: Gateway = match gateway_type {
+                    0 => Gateway::None,
+                    1 => Gateway::V4(r.ipv4()?),
+                    2 => Gateway::V6(r.ipv6()?),
+                    3 => Gateway::Name(r.name()?),
+                    _ => return Err(ParseError::InvalidValue)
+                };
+
+                RData::IPSECKEY {
+                    precedence,
+                    algorithm,
+                    gateway,
+                    public_key: r.rest()?
+                }
+            },
+            QuestionType::RRSIG => RData::RRSIG(Signature::read(&mut r)?),
+            QuestionType::NSEC => RData::NSEC {
+                next: r.name()?,
+                types: r.types()?
+            },
+            QuestionType::DNSKEY => RData::DNSKEY(PublicKey::read(&mut r)?),
+            QuestionType::DHCID => RData::DHCID(r.rest()?),
+            QuestionType::NSEC3 => RData::NSEC3 {
+                algorithm: r.u8()?,
+                flags: r.u8()?,
+                iterations: r.u16()?,
+                salt: r.string()?,
+                next_hashed: r.string()?,
+                types: r.types()?
+            },
+            QuestionType::NSEC3PARAM => RData::NSEC3PARAM {
+                algorithm: r.u8()?,
+                flags: r.u8()?,
+                iterations: r.u16()?,
+                salt: r.string()?
+            },
+            QuestionType::TLSA => RData::TLSA(Association::read(&mut r)?),
+            QuestionType::SMIMEA => RData::SMIMEA(Association::read(&mut r)?),
+            QuestionType::HIP => {
+                let hit_length: usize = r.u8()? as usize;
+                let algorithm: u8 = r.u8()?;
+                let key_length: usize = r.u16()? as usize;
+                let hit: Vec<u8> = r.take(hit_length)?.to_vec();
+                let public_key: Vec<u8> = r.take(key_length)?.to_vec();
+
+                let mut servers: Vec<FQDN> = vec![];
+                while !r.is_done() {
+                    servers.push(r.name()?);
+                }
+
+                RData::HIP {
+                    algorithm,
+                    hit,
+                    public_key,
+                    servers
+                }
+            },
+            QuestionType::NINFO => RData::NINFO(r.strings()?),
+            QuestionType::RKEY => RData::RKEY(PublicKey::read(&mut r)?),
+            QuestionType::TALINK => RData::TALINK {
+                previous: r.name()?,
+                next: r.name()?
+            },
+            QuestionType::CDS => RData::CDS(Digest::read(&mut r)?),
+            QuestionType::CDNSKEY => RData::CDNSKEY(PublicKey::read(&mut r)?),
+            QuestionType::OPENPGPKEY => RData::OPENPGPKEY(r.rest()?),
+            QuestionType::CSYNC => RData::CSYNC {
+                serial: r.u32()?,
+                flags: r.u16()?,
+                types: r.types()?
+            },
+            QuestionType::ZONEMD => RData::ZONEMD {
+                serial: r.u32()?,
+                scheme: r.u8()?,
+                algorithm: r.u8()?,
+                digest: r.rest()?
+            },
+            QuestionType::SVCB => RData::SVCB(ServiceBinding::read(&mut r)?),
+            QuestionType::HTTPS => RData::HTTPS(ServiceBinding::read(&mut r)?),
+            QuestionType::SPF => RData::SPF(r.strings()?),
+            QuestionType::UINFO => RData::UINFO(r.rest()?),
+            QuestionType::UID => RData::UID(r.u32()?),
+            QuestionType::GID => RData::GID(r.u32()?),
+            QuestionType::UNSPEC => RData::UNSPEC(r.rest()?),
+            QuestionType::NID => RData::NID {
+                preference: r.u16()?,
+                node_id: r.u64()?
+            },
+            QuestionType::L32 => RData::L32 {
+                preference: r.u16()?,
+                locator: r.ipv4()?
+            },
+            QuestionType::L64 => RData::L64 {
+                preference: r.u16()?,
+                locator: r.u64()?
+            },
+            QuestionType::LP => RData::LP {
+                preference: r.u16()?,
+                fqdn: r.name()?
+            },
+            QuestionType::EUI48 => RData::EUI48(
+                r.take(6)?.try_into()
+                    .map_err(|_| ParseError::RDataLength)?
+            ),
+            QuestionType::EUI64 => RData::EUI64(
+                r.take(8)?.try_into()
+                    .map_err(|_| ParseError::RDataLength)?
+            ),
+            QuestionType::TKEY => {
+                let algorithm: FQDN = r.name()?;
+                let inception: u32 = r.u32()?;
+                let expiration: u32 = r.u32()?;
+                let mode: u16 = r.u16()?;
+                let error: u16 = r.u16()?;
+                let key_length: usize = r.u16()? as usize;
+                let key: Vec<u8> = r.take(key_length)?.to_vec();
+                let other_length: usize = r.u16()? as usize;
+
+                RData::TKEY {
+                    algorithm,
+                    inception,
+                    expiration,
+                    mode,
+                    error,
+                    key,
+                    other: r.take(other_length)?.to_vec()
+                }
+            },
+            QuestionType::TSIG => {
+                let algorithm: FQDN = r.name()?;
+
+                // Time signed is a 48-bit number of seconds
+                let time_signed: u64 = ((r.u16()? as u64) << 32) | r.u32()? as u64;
+                let fudge: u16 = r.u16()?;
+                let mac_length: usize = r.u16()? as usize;
+                let mac: Vec<u8> = r.take(mac_length)?.to_vec();
+                let original_id: u16 = r.u16()?;
+                let error: u16 = r.u16()?;
+                let other_length: usize = r.u16()? as usize;
+
+                RData::TSIG {
+                    algorithm,
+                    time_signed,
+                    fudge,
+                    mac,
+                    original_id,
+                    error,
+                    other: r.take(other_length)?.to_vec()
+                }
+            },
+            QuestionType::CAA => {
+                let flags: u8 = r.u8()?;
+
+                RData::CAA {
+                    flags,
+                    tag: r.string()?,
+                    value: r.rest()?
+                }
+            },
+
+            /*
+                NIMLOC and EID never got a published wire format, the rest are
+                query only types that never appear in records
+            */
+            QuestionType::NIMLOC |
+            QuestionType::EID |
+            QuestionType::IXFR |
+            QuestionType::AXFR |
+            QuestionType::MAILB |
+            QuestionType::MAILA |
+            QuestionType::ANY |
+            QuestionType::Unknown(..) => RData::Unknown(r.rest()?)
+        };
+
+        // RDATA has to be consumed entirely by the fields
+        if !r.is_done() {
+            return Err(ParseError::RDataLength);
+        }
+
+        // Most types are written without compression, RDLENGTH has to hold them then
+        if length + r.expanded > u16::MAX as usize {
+            return Err(ParseError::RDataLength);
+        }
+
+        Ok(data)
+    }
+
+    /// Serialize RDATA into the message. Only names of the types defined in
+    /// RFC 1035 are compressed, later types must be written uncompressed
+    /// https://www.rfc-editor.org/rfc/rfc3597#section-4
+    ///
+    /// Fields that can't fit their wire format are our own fault, so they
+    /// fail with server failure
+    pub fn bytes(&self, w: &mut MessageWriter) -> Result<(), ResponseCode> {
+        match self {
+            RData::A(address) => w.write_bytes(&address.octets()),
+            RData::NS(name) |
+            RData::MD(name) |
+            RData::MF(name) |
+            RData::CNAME(name) |
+            RData::MB(name) |
+            RData::MG(name) |
+            RData::MR(name) |
+            RData::PTR(name) => write_name(w, name, true),
+            RData::SOA(soa) => {
+                write_name(w, &soa.mname, true);
+                write_name(w, &soa.rname, true);
+                w.write_u32(soa.serial);
+                w.write_u32(soa.refresh);
+                w.write_u32(soa.retry);
+                w.write_u32(soa.expire);
+                w.write_u32(soa.minimum);
+            },
+            RData::NULL(data) |
+            RData::NSAP(data) |
+            RData::DHCID(data) |
+            RData::OPENPGPKEY(data) |
+            RData::UINFO(data) |
+            RData::UNSPEC(data) |
+            RData::Unknown(data) => w.write_bytes(data),
+            RData::WKS { address, protocol, bitmap } => {
+                w.write_bytes(&address.octets());
+                w.write_u8(*protocol);
+                w.write_bytes(bitmap);
+            },
+            RData::HINFO { cpu, os } => {
+                write_string(w, cpu)?;
+                write_string(w, os)?;
+            },
+            RData::MINFO { responsible_mailbox, error_mailbox } => {
+                write_name(w, responsible_mailbox, true);
+                write_name(w, error_mailbox, true);
+            },
+            RData::MX { preference, exchange } => {
+                w.write_u16(*preference);
+                write_name(w, exchange, true);
+            },
+            RData::TXT(strings) |
+            RData::SPF(strings) |
+            RData::NINFO(strings) => {
+                for string in strings {
+                    write_string(w, string)?;
+                }
+            },
+            RData::RP { mailbox, txt } => {
+                write_name(w, mailbox, false);
+                write_name(w, txt, false);
+            },
+            RData::AFSDB { subtype: preference, hostname: name } |
+            RData::RT { preference, intermediate: name } |
+            RData::KX { preference, exchanger: name } |
+            RData::LP { preference, fqdn: name } => {
+                w.write_u16(*preference);
+                write_name(w, name, false);
+            },
+            RData::X25(address) => write_string(w, address)?,
+            RData::ISDN { address, subaddress } => {
+                write_string(w, address)?;
+
+                if let Some(subaddress) = subaddress {
+                    write_string(w, subaddress)?;
+                }
+            },
+            RData::SIG(signature) |
+            RData::RRSIG(signature) => signature.bytes(w),
+            RData::KEY(key) |
+            RData::DNSKEY(key) |
+            RData::RKEY(key) |
+            RData::CDNSKEY(key) => key.bytes(w),
+            RData::PX { preference, map822, mapx400 } => {
+                w.write_u16(*preference);
+                write_name(w, map822, false);
+                write_name(w, mapx400, false);
+            },
+            RData::GPOS { longitude, latitude, altitude } => {
+                write_string(w, longitude)?;
+                write_string(w, latitude)?;
+                write_string(w, altitude)?;
+            },
+            RData::AAAA(address) => w.write_bytes(&address.octets()),
+            RData::LOC { version, size, horizontal_precision, vertical_precision, latitude, longitude, altitude } => {
+                w.write_u8(*version);
+                w.write_u8(*size);
+                w.write_u8(*horizontal_precision);
+                w.write_u8(*vertical_precision);
+                w.write_u32(*latitude);
+                w.write_u32(*longitude);
+                w.write_u32(*altitude);
+            },
+            RData::NXT { next, bitmap } => {
+                write_name(w, next, false);
+                w.write_bytes(bitmap);
+            },
+            RData::SRV { priority, weight, port, target } => {
+                w.write_u16(*priority);
+                w.write_u16(*weight);
+                w.write_u16(*port);
+                write_name(w, target, false);
+            },
+            RData::ATMA { format, address } => {
+                w.write_u8(*format);
+                w.write_bytes(address);
+            },
+            RData::NAPTR { order, preference, flags, services, regexp, replacement } => {
+                w.write_u16(*order);
+                w.write_u16(*preference);
+                write_string(w, flags)?;
+                write_string(w, services)?;
+                write_string(w, regexp)?;
+                write_name(w, replacement, false);
+            },
+            RData::CERT { cert_type, key_tag, algorithm, certificate } => {
+                w.write_u16(*cert_type);
+                w.write_u16(*key_tag);
+                w.write_u8(*algorithm);
+                w.write_bytes(certificate);
+            },
+            RData::DNAME(name) => write_name(w, name, false),
+            RData::OPT(options) => {
+                for option in options {
+                    let length: u16 = u16::try_from(option.data.len())
+                        .map_err(|_| ResponseCode::ServerFailure)?;
+
+                    w.write_u16(option.code);
+                    w.write_u16(length);
+                    w.write_bytes(&option.data);
+                }
+            },
+            RData::APL(prefixes) => {
+                for prefix in prefixes {
+                    let length: u8 = u8::try_from(prefix.address.len())
+                        .ok()
+                        .filter(|length: &u8| *length <= 0x7F)
+                        .ok_or(ResponseCode::ServerFailure)?;
+
+                    w.write_u16(prefix.family);
+                    w.write_u8(prefix.prefix);
+                    w.write_u8(length | if prefix.negation { 0x80 } else { 0x0 });
+                    w.write_bytes(&prefix.address);
+                }
+            },
+            RData::DS(digest) |
+            RData::CDS(digest) => digest.bytes(w),
+            RData::SSHFP { algorithm, fingerprint_type, fingerprint } => {
+                w.write_u8(*algorithm);
+                w.write_u8(*fingerprint_type);
+                w.write_bytes(fingerprint);
+            },
+            RData::IPSECKEY { precedence, algorithm, gateway, public_key } => {
+                w.write_u8(*precedence);
+                w.write_u8(match gateway {
+                    Gateway::None => 0,
+                    Gateway::V4(..) => 1,
+                    Gateway::V6(..) => 2,
+                    Gateway::Name(..) => 3
+                });
+                w.write_u8(*algorithm);
+
+                match gateway {
+                    Gateway::None => {},
+                    Gateway::V4(address) => w.write_bytes(&address.octets()),
+                    Gateway::V6(address) => w.write_bytes(&address.octets()),
+                    Gateway::Name(name) => write_name(w, name, false)
+                };
+
+                w.write_bytes(public_key);
+            },
+            RData::NSEC { next, types } => {
+                write_name(w, next, false);
+                write_types(w, types);
+            },
+            RData::NSEC3 { algorithm, flags, iterations, salt, next_hashed, types } => {
+                w.write_u8(*algorithm);
+                w.write_u8(*flags);
+                w.write_u16(*iterations);
+                write_string(w, salt)?;
+                write_string(w, next_hashed)?;
+                write_types(w, types);
+            },
+            RData::NSEC3PARAM { algorithm, flags, iterations, salt } => {
+                w.write_u8(*algorithm);
+                w.write_u8(*flags);
+                w.write_u16(*iterations);
+                write_string(w, salt)?;
+            },
+            RData::TLSA(association) |
+            RData::SMIMEA(association) => {
+                w.write_u8(association.usage);
+                w.write_u8(association.selector);
+                w.write_u8(association.matching_type);
+                w.write_bytes(&association.data);
+            },
+            RData::HIP { algorithm, hit, public_key, servers } => {
+                w.write_u8(
+                    u8::try_from(hit.len())
+                        .map_err(|_| ResponseCode::ServerFailure)?
+                );
+                w.write_u8(*algorithm);
+                w.write_u16(
+                    u16::try_from(public_key.len())
+                        .map_err(|_| ResponseCode::ServerFailure)?
+                );
+                w.write_bytes(hit);
+                w.write_bytes(public_key);
+
+                for server in servers {
+                    write_name(w, server, false);
+                }
+            },
+            RData::TALINK { previous, next } => {
+                write_name(w, previous, false);
+                write_name(w, next, false);
+            },
+            RData::CSYNC { serial, flags, types } => {
+                w.write_u32(*serial);
+                w.write_u16(*flags);
+                write_types(w, types);
+            },
+            RData::ZONEMD { serial, scheme, algorithm, digest } => {
+                w.write_u32(*serial);
+                w.write_u8(*scheme);
+                w.write_u8(*algorithm);
+                w.write_bytes(digest);
+            },
+            RData::SVCB(binding) |
+            RData::HTTPS(binding) => binding.bytes(w)?,
+            RData::UID(id) |
+            RData::GID(id) => w.write_u32(*id),
+            RData::NID { preference, node_id: locator } |
+            RData::L64 { preference, locator } => {
+                w.write_u16(*preference);
+                w.write_u32((*locator >> 32) as u32);
+                w.write_u32(*locator as u32);
+            },
+            RData::L32 { preference, locator } => {
+                w.write_u16(*preference);
+                w.write_bytes(&locator.octets());
+            },
+            RData::EUI48(address) => w.write_bytes(address),
+            RData::EUI64(address) => w.write_bytes(address),
+            RData::TKEY { algorithm, inception, expiration, mode, error, key, other } => {
+                write_name(w, algorithm, false);
+                w.write_u32(*inception);
+                w.write_u32(*expiration);
+                w.write_u16(*mode);
+                w.write_u16(*error);
+                write_long_string(w, key)?;
+                write_long_string(w, other)?;
+            },
+            RData::TSIG { algorithm, time_signed, fudge, mac, original_id, error, other } => {
+                write_name(w, algorithm, false);
+                w.write_u16((*time_signed >> 32) as u16);
+                w.write_u32(*time_signed as u32);
+                w.write_u16(*fudge);
+                write_long_string(w, mac)?;
+                w.write_u16(*original_id);
+                w.write_u16(*error);
+                write_long_string(w, other)?;
+            },
+            RData::CAA { flags, tag, value } => {
+                w.write_u8(*flags);
+                write_string(w, tag)?;
+                w.write_bytes(value);
+            }
+        };
+
+        Ok(())
+    }
+}
+
+/// Presentation format of the common types, every other type is written in
+/// the generic form, which is valid for any type
+/// https://www.rfc-editor.org/rfc/rfc3597#section-5
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A(address) => write!(f, "{}", address),
+            RData::AAAA(address) => write!(f, "{}", address),
+            RData::NS(name) |
+            RData::CNAME(name) |
+            RData::PTR(name) |
+            RData::DNAME(name) => write!(f, "{}", name),
+            RData::MX { preference, exchange } => write!(f, "{} {}", preference, exchange),
+            RData::SOA(soa) => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                soa.mname, soa.rname, soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum
+            ),
+            RData::SRV { priority, weight, port, target } => write!(
+                f,
+                "{} {} {} {}",
+                priority, weight, port, target
+            ),
+            RData::TXT(strings) |
+            RData::SPF(strings) => {
+                let quoted: Vec<String> = strings.iter()
+                    .map(|string: &Vec<u8>| quote(string))
+                    .collect();
+
+                write!(f, "{}", quoted.join(" "))
+            },
+            _ => {
+                let mut writer = MessageWriter::uncompressed();
+                self.bytes(&mut writer)
+                    .map_err(|_| fmt::Error)?;
+
+                let data: Vec<u8> = writer.finish();
+                if data.is_empty() {
+                    return write!(f, "\\# 0");
+                }
+
+                let hex: String = data.iter()
+                    .map(|byte: &u8| format!("{:02x}", byte))
+                    .collect();
+
+                write!(f, "\\# {} {}", data.len(), hex)
+            }
+        }
+    }
+}
+
+/// Quote <character-string>, unprintable bytes are escaped as \DDD
+fn quote(string: &[u8]) -> String {
+    let mut quoted: String = String::from("\"");
+
+    for byte in string {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(*byte as char);
+            },
+            0x20..=0x7E => quoted.push(*byte as char),
+            _ => quoted += &format!("\\{:03}", byte)
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+impl Signature {
+    fn read(r: &mut RDataReader) -> Result<Self, ParseError> {
+        Ok(Signature {
+            type_covered: r.u16()?,
+            algorithm: r.u8()?,
+            labels: r.u8()?,
+            original_ttl: r.u32()?,
+            expiration: r.u32()?,
+            inception: r.u32()?,
+            key_tag: r.u16()?,
+            signer: r.name()?,
+            signature: r.rest()?
+        })
+    }
+
+    fn bytes(&self, w: &mut MessageWriter) {
+        w.write_u16(self.type_covered);
+        w.write_u8(self.algorithm);
+        w.write_u8(self.labels);
+        w.write_u32(self.original_ttl);
+        w.write_u32(self.expiration);
+        w.write_u32(self.inception);
+        w.write_u16(self.key_tag);
+        write_name(w, &self.signer, false);
+        w.write_bytes(&self.signature);
+    }
+}
+
+impl PublicKey {
+    fn read(r: &mut RDataReader) -> Result<Self, ParseError> {
+        Ok(PublicKey {
+            flags: r.u16()?,
+            protocol: r.u8()?,
+            algorithm: r.u8()?,
+            public_key: r.rest()?
+        })
+    }
+
+    fn bytes(&self, w: &mut MessageWriter) {
+        w.write_u16(self.flags);
+        w.write_u8(self.protocol);
+        w.write_u8(self.algorithm);
+        w.write_bytes(&self.public_key);
+    }
+}
+
+impl Digest {
+    fn read(r: &mut RDataReader) -> Result<Self, ParseError> {
+        Ok(Digest {
+            key_tag: r.u16()?,
+            algorithm: r.u8()?,
+            digest_type: r.u8()?,
+            digest: r.rest()?
+        })
+    }
+
+    fn bytes(&self, w: &mut MessageWriter) {
+        w.write_u16(self.key_tag);
+        w.write_u8(self.algorithm);
+        w.write_u8(self.digest_type);
+        w.write_bytes(&self.digest);
+    }
+}
+
+impl Association {
+    fn read(r: &mut RDataReader) -> Result<Self, ParseError> {
+        Ok(Association {
+            usage: r.u8()?,
+            selector: r.u8()?,
+            matching_type: r.u8()?,
+            data: r.rest()?
+        })
+    }
+}
+
+impl ServiceBinding {
+    fn read(r: &mut RDataReader) -> Result<Self, ParseError> {
+        let priority: u16 = r.u16()?;
+        let target: FQDN = r.name()?;
+
+        let mut params: Vec<(u16, Vec<u8>)> = vec![];
+        while !r.is_done() {
+            let key: u16 = r.u16()?;
+            let length: usize = r.u16()? as usize;
+
+            params.push((key, r.take(length)?.to_vec()));
+        }
+
+        Ok(ServiceBinding {
+            priority,
+            target,
+            params
+        })
+    }
+
+    fn bytes(&self, w: &mut MessageWriter) -> Result<(), ResponseCode> {
+        w.write_u16(self.priority);
+        write_name(w, &self.target, false);
+
+        for (key, value) in &self.params {
+            w.write_u16(*key);
+            write_long_string(w, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Receivers must decompress names of the RFC 1035 types and should for the
+/// few later types listed in RFC 3597, every other type must not use pointers
+/// https://www.rfc-editor.org/rfc/rfc3597#section-4
+fn allows_compression(rr_type: QuestionType) -> bool {
+    matches!(
+        rr_type,
+        QuestionType::NS |
+        QuestionType::MD |
+        QuestionType::MF |
+        QuestionType::CNAME |
+        QuestionType::SOA |
+        QuestionType::MB |
+        QuestionType::MG |
+        QuestionType::MR |
+        QuestionType::PTR |
+        QuestionType::MINFO |
+        QuestionType::MX |
+        QuestionType::RP |
+        QuestionType::AFSDB |
+        QuestionType::RT |
+        QuestionType::SIG |
+        QuestionType::PX |
+        QuestionType::NXT |
+        QuestionType::NAPTR |
+        QuestionType::SRV
+    )
+}
+
+fn write_name(w: &mut MessageWriter, name: &FQDN, compress: bool) {
+    w.write_name(name.labels(), compress);
+}
+
+fn write_string(w: &mut MessageWriter, string: &[u8]) -> Result<(), ResponseCode> {
+    let length: u8 = u8::try_from(string.len())
+        .map_err(|_| ResponseCode::ServerFailure)?;
+
+    w.write_u8(length);
+    w.write_bytes(string);
+
+    Ok(())
+}
+
+/// Data prefixed with two byte length, used by TKEY, TSIG and SVCB
+fn write_long_string(w: &mut MessageWriter, data: &[u8]) -> Result<(), ResponseCode> {
+    let length: u16 = u16::try_from(data.len())
+        .map_err(|_| ResponseCode::ServerFailure)?;
+
+    w.write_u16(length);
+    w.write_bytes(data);
+
+    Ok(())
+}
+
+fn write_types(w: &mut MessageWriter, types: &[u16]) {
+    let mut sorted: Vec<u16> = types.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut i: usize = 0;
+    while i < sorted.len() {
+        let window: u8 = (sorted[i] >> 8) as u8;
+        let mut bitmap: [u8; 32] = [0; 32];
+        let mut length: usize = 0;
+
+        while i < sorted.len() && (sorted[i] >> 8) as u8 == window {
+            let low: usize = (sorted[i] & 0xFF) as usize;
+
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+            length = low / 8 + 1;
+            i += 1;
+        }
+
+        w.write_u8(window);
+        w.write_u8(length as u8);
+        w.write_bytes(&bitmap[0..length]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::dns::{
+        DNS,
+        TransportProto
+    };
+
+    fn read(rr_type: QuestionType, rdata: &[u8]) -> Result<RData, ParseError> {
+        return RData::read(rr_type, rdata, 0, rdata.len());
+    }
+
+    fn write(data: &RData) -> Vec<u8> {
+        let mut writer = MessageWriter::uncompressed();
+        data.bytes(&mut writer).unwrap();
+        return writer.finish();
+    }
+
+    /// Parse uncompressed RDATA and check it's written back byte for byte
+    fn round_trip(rr_type: QuestionType, rdata: &[u8]) -> RData {
+        let data: RData = read(rr_type, rdata).unwrap();
+        assert_eq!(write(&data), rdata);
+        return data;
+    }
+
+    fn name(value: &str) -> FQDN {
+        return FQDN::try_from(value.to_string()).unwrap();
+    }
+
+    #[test]
+    fn names_round_trip() {
+        assert_eq!(
+            round_trip(QuestionType::NS, b"\x02ns\x07example\x00"),
+            RData::NS(name("ns.example"))
+        );
+        assert_eq!(
+            round_trip(QuestionType::MX, b"\x00\x0A\x04mail\x07example\x00"),
+            RData::MX { preference: 10, exchange: name("mail.example") }
+        );
+
+        let mut soa: Vec<u8> = b"\x02ns\x07example\x00\x04host\x07example\x00".to_vec();
+        for value in 1..=5u32 {
+            soa.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let data: RData = round_trip(QuestionType::SOA, &soa);
+        assert_eq!(data.to_string(), "ns.example host.example 1 2 3 4 5");
+    }
+
+    #[test]
+    fn names_in_message_are_decompressed_and_compressed() {
+        // Owner example at 0, MX RDATA at 9 points back to it
+        let message: &[u8] = b"\x07example\x00\x00\x0A\x04mail\xC0\x00";
+
+        let data: RData = RData::read(QuestionType::MX, message, 9, 9).unwrap();
+        assert_eq!(data, RData::MX { preference: 10, exchange: name("mail.example") });
+
+        let mut writer = MessageWriter::new();
+        writer.write_name(name("example").labels(), true);
+        data.bytes(&mut writer).unwrap();
+
+        assert_eq!(writer.finish(), message);
+    }
+
+    #[test]
+    fn compressed_names_are_rejected_where_not_allowed() {
+        let message: &[u8] = b"\x07example\x00\x00\x0A\x04mail\xC0\x00";
+
+        assert!(RData::read(QuestionType::AFSDB, message, 9, 9).is_ok());
+        assert_eq!(
+            RData::read(QuestionType::KX, message, 9, 9).unwrap_err(),
+            ParseError::InvalidPointer
+        );
+        assert_eq!(
+            RData::read(QuestionType::LP, message, 9, 9).unwrap_err(),
+            ParseError::InvalidPointer
+        );
+    }
+
+    /// Owner name of 240 octets at offset 12 of a response header
+    fn message_with_long_owner() -> Vec<u8> {
+        let mut message: Vec<u8> = b"\x00\x00\x81\x80\x00\x00\x00\x01\x00\x00\x00\x00".to_vec();
+
+        for length in [63, 63, 63, 46] {
+            message.push(length);
+            message.extend(vec![b'a'; length as usize]);
+        }
+        message.push(0);
+
+        return message;
+    }
+
+    #[test]
+    fn hip_with_compressed_servers_is_rejected() {
+        // 1900 rendezvous servers pointing to the owner would not fit RDLENGTH uncompressed
+        let mut message: Vec<u8> = message_with_long_owner();
+        message.extend_from_slice(b"\x00\x37\x00\x01\x00\x00\x0E\x10\x0E\xE1");
+        message.extend_from_slice(b"\x04\x02\x00\x01\x11\x22\x33\x44\xAA");
+        for _ in 0..1900 {
+            message.extend_from_slice(b"\xC0\x0C");
+        }
+
+        assert_eq!(message.len(), 4071);
+        assert_eq!(
+            DNS::from(&message, TransportProto::UDP).unwrap_err(),
+            ParseError::InvalidPointer
+        );
+    }
+
+    #[test]
+    fn compressed_rdata_over_rdlength_uncompressed_is_rejected() {
+        // SIG may be compressed, but its signer would be written out in full
+        let mut message: Vec<u8> = message_with_long_owner();
+        let start: usize = message.len();
+        let signature_length: usize = u16::MAX as usize - 18 - 2 - 100;
+
+        message.extend_from_slice(&[0; 18]);
+        message.extend_from_slice(b"\xC0\x0C");
+        message.extend(vec![0; signature_length]);
+
+        let length: usize = message.len() - start;
+        assert_eq!(
+            RData::read(QuestionType::SIG, &message, start, length).unwrap_err(),
+            ParseError::RDataLength
+        );
+
+        // The same fits when the signer only grows within the limit
+        message.truncate(message.len() - 200);
+        assert!(RData::read(QuestionType::SIG, &message, start, length - 200).is_ok());
+    }
+
+    #[test]
+    fn name_past_rdata_is_rejected() {
+        let message: &[u8] = b"\x02ns\x07example\x00";
+
+        assert_eq!(
+            RData::read(QuestionType::NS, message, 0, 4).unwrap_err(),
+            ParseError::RDataLength
+        );
+    }
+
+    #[test]
+    fn character_strings_round_trip() {
+        assert_eq!(
+            round_trip(QuestionType::TXT, b"\x05hello\x00\x02\"\\"),
+            RData::TXT(vec![b"hello".to_vec(), vec![], b"\"\\".to_vec()])
+        );
+        assert_eq!(
+            round_trip(QuestionType::HINFO, b"\x03x86\x05Linux"),
+            RData::HINFO { cpu: b"x86".to_vec(), os: b"Linux".to_vec() }
+        );
+        assert_eq!(
+            round_trip(QuestionType::ISDN, b"\x03123"),
+            RData::ISDN { address: b"123".to_vec(), subaddress: None }
+        );
+
+        let txt: RData = RData::TXT(vec![b"a \"b\"".to_vec(), vec![0x07]]);
+        assert_eq!(txt.to_string(), "\"a \\\"b\\\"\" \"\\007\"");
+    }
+
+    #[test]
+    fn character_string_past_rdata_is_rejected() {
+        assert_eq!(read(QuestionType::TXT, b"\x05hell").unwrap_err(), ParseError::RDataLength);
+    }
+
+    #[test]
+    fn character_string_over_255_octets_fails_to_write() {
+        let txt: RData = RData::TXT(vec![vec![b'a'; 256]]);
+        let mut writer = MessageWriter::uncompressed();
+
+        assert_eq!(txt.bytes(&mut writer).unwrap_err(), ResponseCode::ServerFailure);
+    }
+
+    #[test]
+    fn type_bitmaps_round_trip() {
+        // A, NS, RRSIG, NSEC in window 0 and type 1234 in window 4
+        let bitmap: &[u8] = b"\x00\x06\x60\x00\x00\x00\x00\x03\x04\x1B\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x20";
+        let types: Vec<u16> = vec![1, 2, 46, 47, 1234];
+
+        let mut nsec: Vec<u8> = b"\x04next\x07example\x00".to_vec();
+        nsec.extend_from_slice(bitmap);
+        assert_eq!(
+            round_trip(QuestionType::NSEC, &nsec),
+            RData::NSEC { next: name("next.example"), types: types.clone() }
+        );
+
+        let mut nsec3: Vec<u8> = b"\x01\x00\x00\x0A\x02\xAB\xCD\x03\x01\x02\x03".to_vec();
+        nsec3.extend_from_slice(bitmap);
+        assert_eq!(
+            round_trip(QuestionType::NSEC3, &nsec3),
+            RData::NSEC3 {
+                algorithm: 1,
+                flags: 0,
+                iterations: 10,
+                salt: vec![0xAB, 0xCD],
+                next_hashed: vec![1, 2, 3],
+                types: types.clone()
+            }
+        );
+
+        let mut csync: Vec<u8> = b"\x00\x00\x00\x2A\x00\x03".to_vec();
+        csync.extend_from_slice(bitmap);
+        assert_eq!(
+            round_trip(QuestionType::CSYNC, &csync),
+            RData::CSYNC { serial: 42, flags: 3, types }
+        );
+    }
+
+    #[test]
+    fn type_bitmaps_are_written_sorted_without_duplicates() {
+        let nsec: RData = RData::NSEC { next: FQDN::new(), types: vec![2, 1, 2] };
+
+        assert_eq!(write(&nsec), b"\x00\x00\x01\x60");
+    }
+
+    #[test]
+    fn type_bitmap_window_length_is_checked() {
+        assert_eq!(read(QuestionType::NSEC, b"\x00\x00\x00").unwrap_err(), ParseError::InvalidValue);
+        assert_eq!(read(QuestionType::NSEC, b"\x00\x00\x21").unwrap_err(), ParseError::InvalidValue);
+        assert_eq!(read(QuestionType::NSEC, b"\x00\x00\x02\x40").unwrap_err(), ParseError::RDataLength);
+    }
+
+    #[test]
+    fn apl_prefixes_round_trip() {
+        assert_eq!(
+            round_trip(QuestionType::APL, b"\x00\x01\x18\x03\xC0\xA8\x01\x00\x02\x40\x81\x20"),
+            RData::APL(vec![
+                AddressPrefix { family: 1, prefix: 24, negation: false, address: vec![192, 168, 1] },
+                AddressPrefix { family: 2, prefix: 64, negation: true, address: vec![0x20] }
+            ])
+        );
+        assert_eq!(read(QuestionType::APL, b"\x00\x01\x18\x03\xC0").unwrap_err(), ParseError::RDataLength);
+    }
+
+    #[test]
+    fn ipseckey_gateways_round_trip() {
+        assert_eq!(
+            round_trip(QuestionType::IPSECKEY, b"\x0A\x00\x02\xAA"),
+            RData::IPSECKEY { precedence: 10, algorithm: 2, gateway: Gateway::None, public_key: vec![0xAA] }
+        );
+        round_trip(QuestionType::IPSECKEY, b"\x0A\x01\x02\xC0\x00\x02\x01\xAA");
+        round_trip(QuestionType::IPSECKEY, b"\x0A\x02\x02\x20\x01\x0D\xB8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\xAA");
+
+        assert_eq!(
+            round_trip(QuestionType::IPSECKEY, b"\x0A\x03\x02\x02gw\x07example\x00\xAA"),
+            RData::IPSECKEY {
+                precedence: 10,
+                algorithm: 2,
+                gateway: Gateway::Name(name("gw.example")),
+                public_key: vec![0xAA]
+            }
+        );
+        assert_eq!(read(QuestionType::IPSECKEY, b"\x0A\x04\x02\xAA").unwrap_err(), ParseError::InvalidValue);
+    }
+
+    #[test]
+    fn hip_fields_round_trip() {
+        assert_eq!(
+            round_trip(QuestionType::HIP, b"\x02\x02\x00\x03\x11\x22\xAA\xBB\xCC\x03rvs\x07example\x00\x03rvs\x03net\x00"),
+            RData::HIP {
+                algorithm: 2,
+                hit: vec![0x11, 0x22],
+                public_key: vec![0xAA, 0xBB, 0xCC],
+                servers: vec![name("rvs.example"), name("rvs.net")]
+            }
+        );
+        assert_eq!(
+            round_trip(QuestionType::HIP, b"\x01\x02\x00\x00\x11"),
+            RData::HIP { algorithm: 2, hit: vec![0x11], public_key: vec![], servers: vec![] }
+        );
+        assert_eq!(read(QuestionType::HIP, b"\x02\x02\x00\x03\x11\x22\xAA").unwrap_err(), ParseError::RDataLength);
+    }
+
+    #[test]
+    fn unknown_types_are_kept_raw_and_written_generic() {
+        let data: RData = round_trip(QuestionType::Unknown(65280), b"\x01\x02\x03");
+
+        assert_eq!(data, RData::Unknown(vec![1, 2, 3]));
+        assert_eq!(data.to_string(), "\\# 3 010203");
+        assert_eq!(round_trip(QuestionType::Unknown(65280), b"").to_string(), "\\# 0");
+    }
+
+    #[test]
+    fn known_types_without_presentation_are_written_generic() {
+        let data: RData = round_trip(QuestionType::SSHFP, b"\x01\x02\xAB");
+
+        assert_eq!(data.to_string(), "\\# 3 0102ab");
+    }
+
+    #[test]
+    fn rdlength_mismatch_is_rejected() {
+        assert_eq!(read(QuestionType::A, b"\x7F\x00\x00").unwrap_err(), ParseError::RDataLength);
+        assert_eq!(read(QuestionType::A, b"\x7F\x00\x00\x01\x00").unwrap_err(), ParseError::RDataLength);
+        assert_eq!(read(QuestionType::MX, b"\x00\x0A\x00\x00").unwrap_err(), ParseError::RDataLength);
+        assert_eq!(read(QuestionType::UID, b"\x00\x00\x00").unwrap_err(), ParseError::RDataLength);
+        assert_eq!(
+            RData::read(QuestionType::A, b"\x7F\x00", 0, 4).unwrap_err(),
+            ParseError::UnexpectedEnd
+        );
+    }
+}
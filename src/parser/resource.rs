@@ -1,29 +1,80 @@
 use bitreader::BitReader;
+use std::fmt;
 use super::{
+    error::ParseError,
+    fqdn::FQDN,
+    name,
     qclass::QuestionClass,
-    qtype::QuestionType, 
-    rcode::ResponseCode
+    qtype::QuestionType,
+    rcode::ResponseCode,
+    rdata::RData,
+    writer::MessageWriter
 };
 
 #[derive(Debug, Clone)]
 pub struct DNSResourceFormat {
-    pub name: String,
+    pub name: FQDN,
     pub rr_type: QuestionType,
     pub rr_class: QuestionClass,
     pub ttl: u32,
-    pub length: u16,
-    pub data: Vec<String>,
+    pub data: RData,
 }
 
 impl DNSResourceFormat {
-    pub fn from(reader: &mut BitReader, bytes: &[u8]) -> Result<Self, ResponseCode> {
+    /// Parse one resource record starting at the current reader position
+    /// https://www.ietf.org/rfc/rfc1035.html#section-4.1.3
+    ///
+    /// `bytes` is the whole message the reader was created from, it is needed
+    /// for decompressing names. The reader is advanced past the RDATA section
+    /// on success
+    pub fn from(reader: &mut BitReader, bytes: &[u8]) -> Result<Self, ParseError> {
+        let name: FQDN = name::read_from(reader, bytes)?;
+
+        let rr_type: QuestionType = QuestionType::from(reader.read_u16(16)?);
+        let rr_class: QuestionClass = QuestionClass::from(reader.read_u16(16)?);
+        let ttl: u32 = reader.read_u32(32)?;
+        let length: u16 = reader.read_u16(16)?;
+
+        // Reader is always byte aligned here, every field above is whole octets
+        let start: usize = (reader.position() / 8) as usize;
+        let data: RData = RData::read(rr_type, bytes, start, length as usize)?;
+
+        reader.skip(length as u64 * 8)?;
+
         Ok(DNSResourceFormat {
-            name: String::from("fd"),
-            rr_class: QuestionClass::CH,
-            rr_type: QuestionType::A,
-            ttl: 483943,
-            length: 32,
-            data: vec![String::from("fdlh")]
+            name,
+            rr_type,
+            rr_class,
+            ttl,
+            data
         })
     }
-}
\ No newline at end of file
+
+    /// Serialize the record into the message, RDLENGTH is computed from the
+    /// written RDATA
+    pub fn bytes(&self, writer: &mut MessageWriter) -> Result<(), ResponseCode> {
+        writer.write_name(self.name.labels(), true);
+        writer.write_u16(u16::from(self.rr_type));
+        writer.write_u16(u16::from(self.rr_class));
+        writer.write_u32(self.ttl);
+
+        let length_offset: usize = writer.bytes.len();
+        writer.write_u16(0);
+
+        self.data.bytes(writer)?;
+
+        let length: u16 = u16::try_from(writer.bytes.len() - length_offset - 2)
+            .map_err(|_| ResponseCode::ServerFailure)?;
+        writer.patch_u16(length_offset, length);
+
+        Ok(())
+    }
+}
+
+/// Record in the zone file format, RDATA of types without their own
+/// presentation here is written in the generic form
+impl fmt::Display for DNSResourceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {} {}", self.name, self.ttl, self.rr_class, self.rr_type, self.data)
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use crate::convert_u16_to_two_u8s;
+
+/// Pointers have 14 bits for the offset, names written after this can still
+/// point backwards but can't be pointed to
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
+/// Buffer for serializing the whole message, remembers where every name
+/// suffix was written so later names can be replaced by compression pointers
+/// https://www.ietf.org/rfc/rfc1035.html#section-4.1.4
+pub struct MessageWriter {
+    pub bytes: Vec<u8>,
+
+    /// Offsets of already written name suffixes, labels are lowercased
+    /// because names are compared case insensitively
+    names: HashMap<Vec<Vec<u8>>, u16>,
+
+    /// Pointers are only valid within a whole message, writers of standalone
+    /// RDATA turn compression off entirely
+    compression: bool
+}
+
+impl MessageWriter {
+    pub fn new() -> Self {
+        MessageWriter {
+            bytes: vec![],
+            names: HashMap::new(),
+            compression: true
+        }
+    }
+
+    /// Writer for data that is used outside of a message, no name is ever
+    /// replaced by a pointer
+    pub fn uncompressed() -> Self {
+        MessageWriter {
+            compression: false,
+            ..MessageWriter::new()
+        }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&convert_u16_to_two_u8s!(value, u16));
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&convert_u16_to_two_u8s!(value, u32));
+    }
+
+    /// Overwrite two bytes at `offset` that were written before, used for
+    /// length fields that are only known after the data is written
+    pub fn patch_u16(&mut self, offset: usize, value: u16) {
+        self.bytes[offset..offset + 2].copy_from_slice(&convert_u16_to_two_u8s!(value, u16));
+    }
+
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.bytes.extend_from_slice(value);
+    }
+
+    /// Write domain name, the longest suffix that was already written is
+    /// replaced by a pointer when `compress` is set.
+    ///
+    /// Written suffixes are remembered in both cases, so names of record types
+    /// that must not be compressed can still be pointed to
+    pub fn write_name(&mut self, labels: &[Vec<u8>], compress: bool) {
+        for i in 0..labels.len() {
+            let suffix: Vec<Vec<u8>> = labels[i..].iter()
+                .map(|label: &Vec<u8>| label.to_ascii_lowercase())
+                .collect();
+
+            if compress && self.compression {
+                if let Some(offset) = self.names.get(&suffix) {
+                    self.write_u16(0xC000 | *offset);
+                    return;
+                }
+            }
+
+            if self.bytes.len() <= MAX_POINTER_OFFSET {
+                let offset: u16 = self.bytes.len() as u16;
+                self.names.entry(suffix).or_insert(offset);
+            }
+
+            self.write_u8(labels[i].len() as u8);
+            self.write_bytes(&labels[i]);
+        }
+
+        // Terminating root label
+        self.write_u8(0x0);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(name: &str) -> Vec<Vec<u8>> {
+        return name.split('.')
+            .map(|label: &str| label.as_bytes().to_vec())
+            .collect();
+    }
+
+    #[test]
+    fn repeated_suffix_is_replaced_by_pointer() {
+        let mut writer = MessageWriter::new();
+        writer.write_name(&labels("www.example.com"), true);
+        writer.write_name(&labels("mail.Example.COM"), true);
+        writer.write_name(&labels("www.example.com"), true);
+
+        let mut expected: Vec<u8> = b"\x03www\x07example\x03com\x00".to_vec();
+        expected.extend_from_slice(b"\x04mail\xC0\x04");
+        expected.extend_from_slice(b"\xC0\x00");
+
+        assert_eq!(writer.finish(), expected);
+    }
+
+    #[test]
+    fn uncompressed_names_are_still_pointed_to() {
+        let mut writer = MessageWriter::new();
+        writer.write_name(&labels("example.com"), false);
+        writer.write_name(&labels("example.com"), false);
+        writer.write_name(&labels("example.com"), true);
+
+        let mut expected: Vec<u8> = b"\x07example\x03com\x00".repeat(2);
+        expected.extend_from_slice(b"\xC0\x00");
+
+        assert_eq!(writer.finish(), expected);
+    }
+
+    #[test]
+    fn uncompressed_writer_never_emits_pointers() {
+        let mut writer = MessageWriter::uncompressed();
+        writer.write_name(&labels("example.com"), true);
+        writer.write_name(&labels("example.com"), true);
+
+        assert_eq!(writer.finish(), b"\x07example\x03com\x00".repeat(2));
+    }
+
+    #[test]
+    fn names_beyond_pointer_range_are_not_pointed_to() {
+        let mut writer = MessageWriter::new();
+        writer.write_bytes(&[0; MAX_POINTER_OFFSET + 1]);
+        writer.write_name(&labels("example.com"), true);
+        writer.write_name(&labels("example.com"), true);
+
+        let mut expected: Vec<u8> = vec![0; MAX_POINTER_OFFSET + 1];
+        expected.extend_from_slice(&b"\x07example\x03com\x00".repeat(2));
+
+        assert_eq!(writer.finish(), expected);
+    }
+
+    #[test]
+    fn name_at_last_pointer_offset_is_pointed_to() {
+        let mut writer = MessageWriter::new();
+        writer.write_bytes(&[0; MAX_POINTER_OFFSET]);
+        writer.write_name(&labels("example.com"), true);
+        writer.write_name(&labels("example.com"), true);
+
+        // Only the whole name starts within range, the "com" suffix doesn't
+        let bytes: Vec<u8> = writer.finish();
+        assert_eq!(bytes[bytes.len() - 2..], [0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn patched_length_overwrites_placeholder() {
+        let mut writer = MessageWriter::new();
+        writer.write_u16(0);
+        writer.write_u32(0xDEADBEEF);
+        writer.patch_u16(0, 4);
+
+        assert_eq!(writer.finish(), [0x00, 0x04, 0xDE, 0xAD, 0xBE, 0xEF]);
+    }
+}
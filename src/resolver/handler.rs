@@ -1,22 +1,43 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
+use bitreader::BitReader;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
 use slog::warn;
 use crate::{
     parser::{
         dns::DNS, 
+        edns::Edns,
+        header::DNSHeader,
+        opcode::OpCode,
+        question::DNSQuestion,
         rcode::ResponseCode, 
         r#type::Type
     }, 
-    LOGGER, SOCKET
+    LOGGER, CONFIG
 };
 use super::{question::{
     QuestionHandler, 
-    QuestionHandlerT
+    QuestionHandlerT,
+    Resolution
 }, transport::TransportProto};
 
+/// Where responses to a client go
+pub enum Responder {
+    /// Socket the datagram came in on
+    UDP(Arc<UdpSocket>),
+
+    /// Writer of the TCP connection, it adds the length prefix
+    TCP(mpsc::Sender<Vec<u8>>)
+}
+
 /// This struct takes an ownership of the datagram and will process it.
 pub struct Handler {
     pub datagram: DNS,
-    pub sent_from: Option<SocketAddr>
+    pub sent_from: Option<SocketAddr>,
+
+    /// Connection the datagram came in on, responses are sent through it
+    pub responder: Option<Responder>
 }
 
 #[async_trait::async_trait]
@@ -29,13 +50,20 @@ pub trait HandlerT {
     /// processing
     async fn handle(&mut self, buf: &[u8], from: SocketAddr);
 
-    /// Will send each question to question handler that will check validity of
-    /// each one and then resolve and return back result.
+    /// Will send the question to question handler that will check validity of
+    /// it and then resolve and send back the response.
     /// Can send "fail response" if processing fails
     async fn resolve_questions(&mut self);
 
+    /// Response to the datagram without any records, echoes the ID, opcode,
+    /// RD flag and questions
+    fn response(&self) -> DNS;
+
     /// Helper function for sending responses when resolving fails
-    fn send_fail_response(&mut self, code: ResponseCode);
+    async fn send_fail_response(&mut self, code: ResponseCode);
+
+    /// Send a response back the way the query came
+    async fn send(&mut self, response: DNS);
 }
 
 #[async_trait::async_trait]
@@ -43,62 +71,267 @@ impl HandlerT for Handler {
     fn new() -> Handler {
         Handler { 
             datagram: DNS::new(), 
-            sent_from: None
+            sent_from: None,
+            responder: None
         }
     }
 
-    fn send_fail_response(&mut self, code: ResponseCode) {
+    fn response(&self) -> DNS {
         let mut response_datagram = DNS::new();
 
         response_datagram.header.qr = Type::Response;
-        response_datagram.header.error_code = code;
         response_datagram.header.op_code = self.datagram.header.op_code;
         response_datagram.header.truncated = false;
         response_datagram.header.id = self.datagram.header.id;
+        response_datagram.header.recursion_desired = self.datagram.header.recursion_desired;
+        response_datagram.header.recursion_available = true;
+        response_datagram.questions = self.datagram.questions.clone();
+
+        // Clients that sent an OPT record get one back with our buffer size
+        response_datagram.edns = self.datagram.edns.as_ref()
+            .map(|query: &Edns| {
+                let mut edns: Edns = Edns::new(CONFIG.edns.udp_payload_size);
+                edns.dnssec_ok = query.dnssec_ok;
+                edns
+            });
 
-        SOCKET.send_to::<SocketAddr>(
-            &*response_datagram.bytes().unwrap(), 
-            self.sent_from.unwrap()
-        )
-        .expect("Failed to send!");
+        response_datagram
     }
 
-    async fn resolve_questions(&mut self) {
-        for i in 0..self.datagram.questions.as_ref().unwrap().len() {
-            match QuestionHandler::new()
-                .handle(self.datagram.questions.as_ref().unwrap()[i].clone()).await {
-                    Ok(result_rf) => {
-                        self.datagram.answer.as_mut()
-                            .unwrap()
-                            .push(result_rf);
-                    },
+    async fn send_fail_response(&mut self, code: ResponseCode) {
+        let mut response_datagram: DNS = self.response();
+        response_datagram.set_response_code(code);
 
-                    Err(code) => {
-                        self.send_fail_response(code);
-                        break;
-                    }
+        self.send(response_datagram).await;
+    }
+
+    async fn send(&mut self, response: DNS) {
+        let (responder, to) = match (self.responder.as_ref(), self.sent_from) {
+            (Some(responder), Some(to)) => (responder, to),
+            _ => return
+        };
+
+        /*
+            UDP responses must fit the buffer the client advertised, 512 octets
+            without EDNS, and our own buffer size which is what we advertise
+            back. TCP messages only have the 16 bit length prefix
+            https://www.rfc-editor.org/rfc/rfc6891#section-6.2.5
+        */
+        let limit: usize = match responder {
+            Responder::UDP(..) => self.datagram.max_udp_payload()
+                .min(CONFIG.edns.udp_payload_size.max(512) as usize),
+            Responder::TCP(..) => u16::MAX as usize
+        };
+
+        /*
+            Records we can't serialize are our own fault, the client still
+            gets a server failure instead of waiting for its timeout
+        */
+        let bytes: Vec<u8> = match response.bytes_within(limit) {
+            Ok(bytes) => bytes,
+            Err(code) => {
+                warn!(LOGGER, "Failed to serialize a response"; "To" => to.to_string(), "Error" => format!("{:?}", code));
+
+                let mut failure: DNS = self.response();
+                failure.set_response_code(ResponseCode::ServerFailure);
+
+                match failure.bytes_within(limit) {
+                    Ok(bytes) => bytes,
+                    Err(..) => return
+                }
+            }
+        };
+
+        match responder {
+            Responder::UDP(socket) => {
+                if let Err(e) = socket.send_to(&bytes, to).await {
+                    warn!(LOGGER, "Failed to send a response"; "To" => to.to_string(), "Error" => e.to_string());
                 }
+            },
+
+            // Fails only when the connection is closed already
+            Responder::TCP(sender) => {
+                let _ = sender.send(bytes).await;
+            }
         }
     }
 
+    async fn resolve_questions(&mut self) {
+        let question: DNSQuestion = match self.datagram.questions.as_deref() {
+            Some([question]) => question.clone(),
+            _ => {
+                self.send_fail_response(ResponseCode::FormatError).await;
+                return;
+            }
+        };
+
+        let resolution: Resolution = match QuestionHandler::new().handle(question).await {
+            Ok(resolution) => resolution,
+            Err(code) => {
+                self.send_fail_response(code).await;
+                return;
+            }
+        };
+
+        // Name error and empty answer are both sent with their authority section
+        let mut response_datagram: DNS = self.response();
+        for (section, records) in [
+            (&mut response_datagram.answer, resolution.answer),
+            (&mut response_datagram.authority, resolution.authority),
+            (&mut response_datagram.additional, resolution.additional)
+        ] {
+            if !records.is_empty() {
+                *section = Some(records);
+            }
+        }
+
+        response_datagram.set_response_code(resolution.code);
+        self.send(response_datagram).await;
+    }
+
     async fn handle(&mut self, buf: &[u8], from: SocketAddr) {
         self.sent_from = Some(from);
 
-        match DNS::from(&*buf, TransportProto::UDP) {
+        // TCP messages still have their length prefix
+        let proto: TransportProto = match self.responder {
+            Some(Responder::TCP(..)) => TransportProto::TCP,
+            _ => TransportProto::UDP
+        };
+
+        match DNS::from(buf, proto) {
             Ok(result) => {
                 self.datagram = result;
-                self.resolve_questions().await;
+
+                // Responses are never answered, that could start a loop between two servers
+                if self.datagram.header.qr == Type::Response {
+                    return;
+                }
+
+                // Version 0 is the only one there is
+                if self.datagram.edns.as_ref().is_some_and(|edns: &Edns| edns.version != 0) {
+                    self.send_fail_response(ResponseCode::BadVersion).await;
+                    return;
+                }
+
+                match self.datagram.header.op_code {
+                    /*
+                        Exactly one question, nobody agrees on what more of
+                        them in one message would mean
+                        https://www.rfc-editor.org/rfc/rfc9619
+                    */
+                    OpCode::Query if self.datagram.header.question_count != 1 => {
+                        self.datagram.questions = None;
+                        self.send_fail_response(ResponseCode::FormatError).await;
+                    },
+
+                    OpCode::Query => self.resolve_questions().await,
+
+                    /*
+                        Inverse queries are obsolete and a recursive resolver
+                        has no zones to notify, update or keep sessions for
+                        https://www.rfc-editor.org/rfc/rfc3425#section-3
+                    */
+                    _ => self.send_fail_response(ResponseCode::NotImplemented).await
+                }
             },
 
             Err(..) => {
+                let header: Option<DNSHeader> = DNSHeader::try_from(&mut BitReader::new(buf), proto).ok();
+
+                // Malformed responses are dropped just as silently
+                if header.as_ref().is_some_and(|header: &DNSHeader| header.qr == Type::Response) {
+                    return;
+                }
+
                 warn!(
                     LOGGER, 
                     "An invalid datagram was sent";
                     "Sent from: " => from.to_string()
                 );
 
-                self.send_fail_response(ResponseCode::FormatError);
+                // ID and opcode are still echoed if at least the header can be read
+                if let Some(header) = header {
+                    self.datagram.header = header;
+
+                    /*
+                        UPDATE and NOTIFY bodies carry records a query never
+                        does (e.g. class ANY with no RDATA), so failing to
+                        parse them says nothing about their format
+                        https://www.rfc-editor.org/rfc/rfc2136#section-2.5.2
+                    */
+                    if self.datagram.header.op_code != OpCode::Query {
+                        return self.send_fail_response(ResponseCode::NotImplemented).await;
+                    }
+                }
+
+                self.send_fail_response(ResponseCode::FormatError).await;
             }
         };
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands a TCP message to a handler and parses what it sends back
+    async fn respond(message: &[u8]) -> DNS {
+        let (sender, mut receiver) = mpsc::channel::<Vec<u8>>(1);
+
+        let mut prefixed: Vec<u8> = (message.len() as u16).to_be_bytes().to_vec();
+        prefixed.extend_from_slice(message);
+
+        let mut handler: Handler = Handler::new();
+        handler.responder = Some(Responder::TCP(sender));
+        handler.handle(&prefixed, "127.0.0.1:53".parse().unwrap()).await;
+
+        let bytes: Vec<u8> = receiver.recv().await.unwrap();
+        return DNS::from(&bytes, TransportProto::UDP).unwrap();
+    }
+
+    #[tokio::test]
+    async fn update_is_not_implemented() {
+        let message: Vec<u8> = [
+            // ID, opcode UPDATE, one zone and one update record
+            &[0x12, 0x34, 0x28, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00][..],
+
+            // Zone example.com SOA IN
+            b"\x07example\x03com\x00", &[0x00, 0x06, 0x00, 0x01],
+
+            // Delete the A RRset of a.example.com, class ANY without RDATA
+            b"\x01a\xc0\x0c", &[0x00, 0x01, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+        ].concat();
+
+        let response: DNS = respond(&message).await;
+
+        assert_eq!(response.header.id, 0x1234);
+        assert_eq!(response.header.qr, Type::Response);
+        assert_eq!(response.header.op_code, OpCode::Update);
+        assert_eq!(response.response_code(), ResponseCode::NotImplemented);
+    }
+
+    #[tokio::test]
+    async fn notify_is_not_implemented() {
+        let message: Vec<u8> = [
+            // ID, opcode NOTIFY with AA set, one question
+            &[0x43, 0x21, 0x24, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00][..],
+            b"\x07example\x03com\x00", &[0x00, 0x06, 0x00, 0x01]
+        ].concat();
+
+        let response: DNS = respond(&message).await;
+
+        assert_eq!(response.header.id, 0x4321);
+        assert_eq!(response.header.op_code, OpCode::Notify);
+        assert_eq!(response.response_code(), ResponseCode::NotImplemented);
+    }
+
+    #[tokio::test]
+    async fn garbled_query_is_format_error() {
+        // Header promises a question that is not there
+        let message: [u8; 12] = [0x55, 0x55, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        let response: DNS = respond(&message).await;
+
+        assert_eq!(response.header.id, 0x5555);
+        assert_eq!(response.response_code(), ResponseCode::FormatError);
+    }
+}
@@ -1,27 +1,96 @@
-use std::{net::{SocketAddr}, str::FromStr};
-use redis::Commands;
-use fancy_regex::Regex;
+use std::{net::{SocketAddr, IpAddr}, collections::HashSet, time::{Duration, Instant}};
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, AtomicU16, Ordering}
+};
+use rand::{rngs::OsRng, Rng};
+use slog::{debug, warn};
 use crate::{parser::{
     question::DNSQuestion, 
     rcode::ResponseCode, 
-    resource::DNSResourceFormat, dns::DNS, r#type::Type, opcode::OpCode, qtype::QuestionType, qclass::QuestionClass
-}, CACHEMANAGER, 
-    cache::modules::rootserver::RootServer,
+    resource::DNSResourceFormat, dns::DNS, r#type::Type, opcode::OpCode, qtype::QuestionType,
+    edns::Edns,
+    fqdn::FQDN,
+    rdata::RData,
+    qclass::QuestionClass
+}, CONFIG, LOGGER, INFRACACHE,
+    cache::def,
+    cache::modules::infrastructure::InfraCacheT,
+    cache::modules::{negative, rrset},
+    helpers::config::AddressFamily
 };
 use super::transport;
 
+/// Referrals followed for one question before giving up, real delegation
+/// chains are a handful of zones deep
+const MAX_REFERRALS: usize = 16;
+
+/// Aliases followed for one question, longer chains are answered with a
+/// server failure
+const MAX_ALIASES: usize = 8;
+
+/// Servers asked for one client question including every nameserver address
+/// resolved on the way, shared by all sub-resolutions. Retransmissions to the
+/// same server and its TCP retry after truncation count as one
+const MAX_QUERIES: usize = 64;
+
+/// Next query ID when random IDs are turned off
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// What one response says about the name being resolved
+enum Step {
+    /// Records of the asked type, empty if the name has none
+    Answer(Vec<DNSResourceFormat>),
+
+    /// Name is an alias, the records lead to the new name
+    Alias(Vec<DNSResourceFormat>, FQDN),
+
+    /// DNAME whose target would make the name longer than 255 octets
+    /// https://www.rfc-editor.org/rfc/rfc6672#section-2.2
+    TooLong(DNSResourceFormat)
+}
+
+/// Outcome of a question that was resolved, either records, a proof from
+/// the authoritative servers that there are none or a DNAME the name can't
+/// be substituted by
+pub struct Resolution {
+    /// No error or name error, anything else is returned as an error
+    pub code: ResponseCode,
+
+    /// Alias chain followed by the records of the asked type
+    pub answer: Vec<DNSResourceFormat>,
+
+    /// Authority section of the last response, nameservers of the zone for
+    /// answers and its SOA record when there are no records
+    pub authority: Vec<DNSResourceFormat>,
+
+    /// Addresses of the nameservers in `authority` that came with them
+    pub additional: Vec<DNSResourceFormat>
+}
+
 pub struct QuestionHandler {
     /// Holding the question by the end user
     question: Option<DNSQuestion>,
 
-    /// Root server assigned for this particular question
-    root_server: Option<RootServer>,
+    /// Nameservers of the closest zone known so far, once resolution is done
+    /// these are the servers that are authoritative for the question
+    authoritative_ns: Option<Vec<SocketAddr>>,
 
-    /// TLD nameserver assigned for this particular question
-    tld_ns: Option<SocketAddr>,
+    /// Queries sent so far, shared with the sub-resolutions of nameserver
+    /// addresses so the whole tree of them has one budget
+    queries: Arc<AtomicUsize>,
 
-    /// Authoritative nameserver assigned for this particular question
-    authoritative_ns: Option<Vec<SocketAddr>>
+    /// Nameserver names whose addresses are being resolved by this handler
+    /// or the ones that started it, needing any of them again is a cycle
+    pending: Vec<FQDN>
+}
+
+/// Nameservers of a zone cut taken from a referral, glue addresses are
+/// empty if the referral didn't include any
+struct Delegation {
+    cut: FQDN,
+    targets: Vec<FQDN>,
+    glue: Vec<SocketAddr>
 }
 
 #[async_trait::async_trait]
@@ -29,22 +98,56 @@ pub trait QuestionHandlerT {
     /// Create a new instance of question handler
     fn new() -> QuestionHandler;
 
-    /// Handle new domain name
+    /// Handle new domain name, the answer is empty if the name doesn't exist
+    /// or exists but has no records of the type
     async fn handle(
         &mut self, inp: DNSQuestion
-    ) -> Result<DNSResourceFormat, ResponseCode>;
+    ) -> Result<Resolution, ResponseCode>;
+
+    /// IPv4 and IPv6 addresses of the root servers cached by the cache manager
+    async fn root_servers() -> Result<Vec<SocketAddr>, ResponseCode>;
+
+    /// Send the question to the servers of `zone` one after another, fastest
+    /// and preferred address family first, until one of them gives a usable
+    /// response, see `useful`. Only records passing `accept` are left in the
+    /// response. Every server asked counts into `queries`
+    async fn query(
+        servers: &[SocketAddr], question: &DNSQuestion, zone: &FQDN, queries: &AtomicUsize
+    ) -> Result<DNS, ResponseCode>;
+
+    /// Walk the delegations from the root down to the servers authoritative
+    /// for the question, returns their response with the zone they serve.
+    /// Name error responses are returned too, they end the walk
+    /// https://www.rfc-editor.org/rfc/rfc1034#section-5.3.3
+    async fn iterate(&mut self, question: &DNSQuestion) -> Result<(FQDN, DNS), ResponseCode>;
 
-    /// Check if TLD exists in IANA database
-    async fn check_if_exists(name: &String) -> bool;
+    /// Addresses of the first nameserver that can be resolved, used for
+    /// referrals without glue
+    async fn glueless(&mut self, targets: &[FQDN]) -> Result<Vec<SocketAddr>, ResponseCode>;
 
-    /// Check is string is fully qualified domain name
-    /// 
-    /// This function does not check for TLD validity, only for the 
-    /// compelete fqdn pattern
-    fn check_fqdn_validity(fqdn: &String) -> bool;
+    /// Closest zone at or above `name` whose delegation and some of its
+    /// nameserver addresses are cached, iteration starts there instead of the root
+    async fn closest_delegation(name: &FQDN) -> Option<(FQDN, Vec<SocketAddr>)>;
 
-    /// Will get available root server and 
-    async fn query_rootserver(&mut self) -> Result<(), ResponseCode>;
+    /// Nameserver addresses cached by an earlier sub-resolution or taken
+    /// from glue
+    async fn cached_addresses(name: &FQDN) -> Option<Vec<SocketAddr>>;
+
+    /// Cache nameserver addresses for `ttl` seconds
+    async fn cache_addresses(name: &FQDN, addresses: &[SocketAddr], ttl: u32);
+
+    /// Negative answer cached for the question with its SOA record, the TTL
+    /// of the SOA is the time the answer has left in the cache
+    async fn cached_negative(question: &DNSQuestion) -> Option<(ResponseCode, DNSResourceFormat)>;
+
+    /// Cache a name error or an empty answer for as long as the SOA record
+    /// in `authority` allows, answers without SOA are not cached
+    /// https://www.rfc-editor.org/rfc/rfc2308#section-5
+    async fn cache_negative(question: &DNSQuestion, code: ResponseCode, authority: &[DNSResourceFormat]);
+
+    /// Resolve the question and every alias on the way, the answer starts
+    /// with the CNAME and DNAME records of the chain
+    async fn resolve(&mut self) -> Result<Resolution, ResponseCode>;
 }
 
 #[async_trait::async_trait]
@@ -52,145 +155,1076 @@ impl QuestionHandlerT for QuestionHandler {
     fn new() -> QuestionHandler {
         QuestionHandler { 
             question: None,
-            root_server: None,
-            tld_ns: None,
-            authoritative_ns: None
+            authoritative_ns: None,
+            queries: Arc::new(AtomicUsize::new(0)),
+            pending: vec![]
         }
     }
 
-    fn check_fqdn_validity(fqdn: &String) -> bool {
-        Regex::from_str(r"(?=^.{4,253}$)(^((?!-)[a-zA-Z0-9-]{1,63}(?<!-)\.)+[a-zA-Z]{2,63}$)")
-            .unwrap()
-            .is_match(fqdn.as_str())
-            .unwrap()
+    async fn handle(&mut self, inp: DNSQuestion) -> Result<Resolution, ResponseCode> {
+        /*
+            Any name is resolved as it is, the root, service labels like _dmarc
+            and labels that aren't hostnames are all valid in the DNS. Whether
+            a name exists is only for its authoritative servers to say
+        */
+        self.question = Some(inp);
+
+        self.resolve().await
     }
 
-    async fn check_if_exists(name: &String) -> bool {
-        let mut cm = CACHEMANAGER.lock().await;
-        let r_inst = cm
-            .redis_instance
-            .as_mut()
-            .unwrap()
-            .get_mut();
+    async fn root_servers() -> Result<Vec<SocketAddr>, ResponseCode> {
+        // Entries are in ttl_type_address format separated by spaces
+        let mut entries: String = String::new();
+        for key in ["ROOTS:A", "ROOTS:AAAA"] {
+            if let Some(value) = def::resource(key).await {
+                entries += &String::from_utf8_lossy(&value);
+            }
+        }
 
-        match r_inst.get::<String, String>(format!("TLD:{}", name)) {
-            Ok(..) => return true,
-            Err(..) => return false
-        };
+        let servers: Vec<SocketAddr> = entries.split_whitespace()
+            .filter_map(|entry: &str| entry.split('_').nth(2))
+            .filter_map(|address: &str| address.parse::<IpAddr>().ok())
+            .map(|address: IpAddr| SocketAddr::new(address, 53))
+            .collect();
+
+        if servers.is_empty() {
+            return Err(ResponseCode::ServerFailure);
+        }
+
+        Ok(servers)
     }
 
-    async fn handle(&mut self, inp: DNSQuestion) -> Result<DNSResourceFormat, ResponseCode> {
-        self.question = Some(inp);
+    async fn query(servers: &[SocketAddr], question: &DNSQuestion, zone: &FQDN, queries: &AtomicUsize) -> Result<DNS, ResponseCode> {
+        // Iterative queries, the servers are not asked to recurse
+        let mut datagram = DNS::new();
+        datagram.header.qr = Type::Query;
+        datagram.header.recursion_desired = false;
+        datagram.header.op_code = OpCode::Query;
+        datagram.header.id = query_id();
+        datagram.edns = Some(Edns::new(CONFIG.edns.udp_payload_size));
 
-        // Rewrite this in FQDN struct later
-        
-        let valid = Self::check_fqdn_validity(
-            &self.question.as_ref() 
-                .unwrap()
-                .name
-                .to_string()
-        );
+        let sent: DNSQuestion = match CONFIG.resolver.randomize_case {
+            true => DNSQuestion {
+                name: mix_case(&question.name),
+                ..question.clone()
+            },
+            false => question.clone()
+        };
+        datagram.questions = Some(vec![sent.clone()]);
 
-        if !valid {
-            return Err(
-                ResponseCode::NameError
-            )
+        let id: u16 = datagram.header.id;
+        let payload: Vec<u8> = datagram.bytes()?;
+
+        let family: AddressFamily = CONFIG.resolver.address_family;
+        let allowed: Vec<SocketAddr> = servers.iter()
+            .filter(|server: &&SocketAddr| family.allows(&server.ip()))
+            .copied()
+            .collect();
+
+        let mut ordered: Vec<SocketAddr> = match INFRACACHE.lock() {
+            Ok(mut cache) => cache.order(&allowed),
+            Err(..) => allowed
+        };
+
+        // Stable sort keeps the order by round trip time within a family
+        ordered.sort_by_key(|server: &SocketAddr| family.rank(&server.ip()));
+
+        for server in &ordered {
+            if queries.fetch_add(1, Ordering::Relaxed) >= MAX_QUERIES {
+                return Err(ResponseCode::ServerFailure);
+            }
+
+            let started: Instant = Instant::now();
+            let mut response: DNS = match transport::onetime_transport(
+                &payload, 
+                *server,
+                None
+            ).await {
+                Ok(response) => response,
+                Err(..) => {
+                    record(server, None);
+                    continue;
+                }
+            };
+
+            if !accept(&mut response, id, &sent, zone, server) {
+                record(server, None);
+                continue;
+            }
+
+            /*
+                Failures and lame or upward referrals of one server say nothing
+                about the zone, the next server is asked instead
+            */
+            if useful(&response, question, zone) {
+                record(server, Some(started.elapsed()));
+                return Ok(response);
+            }
+
+            record(server, None);
         }
-        
-        let exists: bool = Self::check_if_exists(
-            &&self.question.as_ref()
-                .unwrap()
-                .name
-                .tld
-        ).await;
 
-        if !exists {
-            return Err(
-                ResponseCode::NameError
-            );
+        Err(ResponseCode::ServerFailure)
+    }
+
+    async fn iterate(&mut self, question: &DNSQuestion) -> Result<(FQDN, DNS), ResponseCode> {
+        let (mut zone, servers): (FQDN, Vec<SocketAddr>) = match Self::closest_delegation(&question.name).await {
+            Some(delegation) => delegation,
+            None => (FQDN::new(), Self::root_servers().await?)
+        };
+        self.authoritative_ns = Some(servers);
+
+        for _ in 0..MAX_REFERRALS {
+            let servers: &[SocketAddr] = self.authoritative_ns.as_deref()
+                .unwrap_or_default();
+
+            let response: DNS = Self::query(servers, question, &zone, &self.queries).await?;
+
+            // Useful responses that aren't a referral are the answer or a proof there is none
+            let delegation: Delegation = match referral(&response, &zone, &question.name) {
+                Some(delegation) if !answered(&response) => delegation,
+                _ => return Ok((zone, response))
+            };
+            cache_delegation(&response, &delegation).await;
+
+            let cut: FQDN = delegation.cut;
+            let next: Vec<SocketAddr> = match delegation.glue.is_empty() {
+                true => self.glueless(&delegation.targets).await?,
+                false => delegation.glue
+            };
+
+            self.authoritative_ns = Some(next);
+            zone = cut;
         }
 
-        match Self::query_rootserver(&mut self).await {
-            Ok(..) => {
+        Err(ResponseCode::ServerFailure)
+    }
 
-            },
+    async fn glueless(&mut self, targets: &[FQDN]) -> Result<Vec<SocketAddr>, ResponseCode> {
+        for target in targets {
+            if self.pending.contains(target) {
+                continue;
+            }
 
-            Err(..) => {
-                return Err(
-                    ResponseCode::ServerFailure
-                );
+            if let Some(addresses) = Self::cached_addresses(target).await {
+                return Ok(addresses);
+            }
+
+            // Preferred family first, the other one only if it has no addresses
+            for qtype in address_types() {
+                let mut handler: QuestionHandler = QuestionHandler::new();
+                handler.queries = self.queries.clone();
+                handler.pending = self.pending.clone();
+                handler.pending.push(target.clone());
+                handler.question = Some(DNSQuestion {
+                    name: target.clone(),
+                    qtype,
+                    class: QuestionClass::IN
+                });
+
+                let records: Vec<DNSResourceFormat> = match handler.resolve().await {
+                    Ok(resolution) if resolution.code == ResponseCode::NoError => resolution.answer,
+                    _ => continue
+                };
+
+                let addresses: Vec<SocketAddr> = records.iter()
+                    .filter_map(server_address)
+                    .collect();
+
+                // Whole chain has to stay valid for the addresses to be
+                let ttl: u32 = records.iter()
+                    .map(|record: &DNSResourceFormat| record.ttl)
+                    .min()
+                    .unwrap_or_default();
+
+                if !addresses.is_empty() {
+                    Self::cache_addresses(target, &addresses, ttl).await;
+                    return Ok(addresses);
+                }
             }
         }
 
-        /*
-            This code is here only for now because resource record 
-            format is not implemented
-        */
         Err(ResponseCode::ServerFailure)
     }
 
-    async fn query_rootserver(&mut self) -> Result<(), ResponseCode> {
-        let mut cm = CACHEMANAGER.lock().await;
-        let r_inst = cm
-            .redis_instance
-            .as_mut()
-            .unwrap()
-            .get_mut();
+    async fn closest_delegation(name: &FQDN) -> Option<(FQDN, Vec<SocketAddr>)> {
+        for depth in 0..name.labels().len() {
+            let zone: FQDN = FQDN::from_labels(name.labels()[depth..].to_vec()).ok()?;
+            let nameservers: Option<Vec<DNSResourceFormat>> = rrset::get_delegation(&zone)
+                .await;
+
+            let mut addresses: Vec<SocketAddr> = vec![];
+            for record in nameservers.iter().flatten() {
+                if let RData::NS(target) = &record.data {
+                    addresses.extend(Self::cached_addresses(target).await.unwrap_or_default());
+                }
+            }
+
+            if !addresses.is_empty() {
+                return Some((zone, addresses));
+            }
+        }
+
+        None
+    }
+
+    async fn cached_addresses(name: &FQDN) -> Option<Vec<SocketAddr>> {
+        let (entries, _): (Vec<u8>, u32) = def::get(&format!("NS:{}", name.to_string().to_lowercase()))
+            .await?;
+        let entries: String = String::from_utf8(entries).ok()?;
+
+        let addresses: Vec<SocketAddr> = entries.split_whitespace()
+            .filter_map(|address: &str| address.parse::<SocketAddr>().ok())
+            .collect();
+
+        if addresses.is_empty() {
+            return None;
+        }
+
+        Some(addresses)
+    }
+
+    async fn cache_addresses(name: &FQDN, addresses: &[SocketAddr], ttl: u32) {
+        let entries: String = addresses.iter()
+            .map(|address: &SocketAddr| address.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
 
-        let r_inst = r_inst.get::<&str, String>("ROOTS:A")
-            .unwrap();
-        /*
-            Cache manager will be dropped at the end of the file which is
-            after the request and that's extra latency. Instead it will be 
-            freed here
-        */
-        std::mem::drop(cm);
-
-        let question = self.question
-            .as_ref()
-            .unwrap();
-
-        // Building question for root server
-        let mut root_s_datagram = DNS::new();
-        root_s_datagram.header.qr = Type::Query;
-        root_s_datagram.header.truncated = false;
-        root_s_datagram.header.recursion_desired = true;
-        root_s_datagram.header.question_count = 1;
-        root_s_datagram.header.op_code = OpCode::Query;
-        root_s_datagram.header.id = 10039;
-        root_s_datagram.questions = Some(vec![DNSQuestion { 
-            name: question.name.clone(), 
-            qtype: QuestionType::NS, 
-            class: QuestionClass::IN 
-        }]);
-
-        let hostname = r_inst.split(" ")
-            .into_iter()
-            .next()
-            .unwrap()
-            .split("_")
-            .nth(2)
-            .unwrap();
-        
         /*
-            Create transport that will handle the whole TCP/UDP mess situation
+            Failing to cache only means the addresses get resolved again
+            next time, the resolution itself succeeded
         */
-        let pkt: DNS = match transport::onetime_transport(
-            &root_s_datagram.bytes().unwrap(), 
-            format!("{}:{}", hostname, 53).parse::<SocketAddr>()
-                .unwrap(),
-            None
-        ).await {
-            Ok(packet) => packet,
-            Err(..) => {
-                return Result::Err(
-                    ResponseCode::ServerFailure
+        def::set(
+            format!("NS:{}", name.to_string().to_lowercase()),
+            entries.into_bytes(),
+            ttl
+        ).await;
+    }
+
+    async fn cached_negative(question: &DNSQuestion) -> Option<(ResponseCode, DNSResourceFormat)> {
+        // Name that doesn't exist has no records of any type
+        for (key, code) in [
+            (negative::key(&question.name, None), ResponseCode::NameError),
+            (negative::key(&question.name, Some((question.qtype, question.class))), ResponseCode::NoError)
+        ] {
+            let soa: DNSResourceFormat = match def::get(&key).await {
+                Some((bytes, remaining)) => match negative::decode(&bytes, remaining) {
+                    Some(soa) => soa,
+                    None => continue
+                },
+                None => continue
+            };
+
+            return Some((code, soa));
+        }
+
+        None
+    }
+
+    async fn cache_negative(question: &DNSQuestion, code: ResponseCode, authority: &[DNSResourceFormat]) {
+        let soa: &DNSResourceFormat = match negative::soa(authority) {
+            Some(soa) => soa,
+            None => return
+        };
+
+        let key: String = match code {
+            ResponseCode::NameError => negative::key(&question.name, None),
+            _ => negative::key(&question.name, Some((question.qtype, question.class)))
+        };
+
+        if let Some(bytes) = negative::encode(soa) {
+            def::set(key, bytes, negative::ttl(soa)).await;
+        }
+    }
+
+    async fn resolve(&mut self) -> Result<Resolution, ResponseCode> {
+        let mut question: DNSQuestion = self.question.clone()
+            .ok_or(ResponseCode::ServerFailure)?;
+
+        let mut chain: Vec<DNSResourceFormat> = vec![];
+        let mut visited: HashSet<FQDN> = HashSet::from([question.name.clone()]);
+
+        loop {
+            if let Some((code, soa)) = Self::cached_negative(&question).await {
+                return Ok(Resolution {
+                    code,
+                    answer: chain,
+                    authority: vec![soa],
+                    additional: vec![]
+                });
+            }
+
+            match cached_step(&question).await {
+                Some(Step::Answer(records)) => {
+                    chain.extend(records);
+
+                    return Ok(Resolution {
+                        code: ResponseCode::NoError,
+                        answer: chain,
+                        authority: vec![],
+                        additional: vec![]
+                    });
+                },
+
+                Some(Step::Alias(records, target)) => {
+                    chain.extend(records);
+
+                    if visited.len() > MAX_ALIASES || !visited.insert(target.clone()) {
+                        return Err(ResponseCode::ServerFailure);
+                    }
+
+                    question.name = target;
+                    continue;
+                },
+
+                Some(Step::TooLong(..)) | None => {}
+            }
+
+            let (zone, response): (FQDN, DNS) = self.iterate(&question).await?;
+            let mut followed: bool = false;
+
+            loop {
+                match step(&response, &question) {
+                    /*
+                        Missing records of an alias target that was taken from
+                        the same response only mean the server didn't include
+                        them, so the target is resolved on its own. A name
+                        error is about the last name of the chain though
+                        https://www.rfc-editor.org/rfc/rfc6604#section-2
+                    */
+                    Step::Answer(records) if records.is_empty() && followed &&
+                        response.response_code() != ResponseCode::NameError => break,
+
+                    Step::Answer(records) => {
+                        match records.is_empty() {
+                            true => Self::cache_negative(
+                                &question,
+                                response.response_code(),
+                                response.authority.as_deref().unwrap_or_default()
+                            ).await,
+
+                            false => rrset::set(&records).await
+                        }
+
+                        chain.extend(records);
+
+                        debug!(
+                            LOGGER,
+                            "Question resolved";
+                            "name" => question.name.to_string(),
+                            "zone" => zone.to_string(),
+                            "authoritative" => format!("{:?}", self.authoritative_ns)
+                        );
+
+                        return Ok(Resolution {
+                            code: response.response_code(),
+                            answer: chain,
+                            authority: response.authority.clone().unwrap_or_default(),
+                            additional: nameserver_addresses(&response)
+                        });
+                    },
+
+                    // The DNAME is part of the answer, it's why the name is too long
+                    Step::TooLong(dname) => {
+                        rrset::set(std::slice::from_ref(&dname)).await;
+                        chain.push(dname);
+
+                        return Ok(Resolution {
+                            code: ResponseCode::YXDomain,
+                            answer: chain,
+                            authority: vec![],
+                            additional: vec![]
+                        });
+                    },
+
+                    Step::Alias(records, target) => {
+                        rrset::set(&records).await;
+                        chain.extend(records);
+
+                        if visited.len() > MAX_ALIASES || !visited.insert(target.clone()) {
+                            return Err(ResponseCode::ServerFailure);
+                        }
+
+                        question.name = target;
+                        followed = true;
+
+                        // Only records from the zone of the server are trusted for the target
+                        if !question.name.is_subdomain_of(&zone) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Records of the question from the RRset cache, or the alias its name leads
+/// to if the name is a cached CNAME
+async fn cached_step(question: &DNSQuestion) -> Option<Step> {
+    if let Some(records) = rrset::get(&question.name, question.qtype, question.class).await {
+        return Some(Step::Answer(records));
+    }
+
+    if question.qtype == QuestionType::CNAME {
+        return None;
+    }
+
+    let records: Vec<DNSResourceFormat> = rrset::get(&question.name, QuestionType::CNAME, question.class).await?;
+    let target: FQDN = match &records.first()?.data {
+        RData::CNAME(target) => target.clone(),
+        _ => return None
+    };
+
+    Some(Step::Alias(records, target))
+}
+
+/// Remember the nameservers of a referral and their glue, so later questions
+/// below the cut can start from its servers. The nameservers are kept apart
+/// from the RRsets and never overwrite the NS records of the zone itself
+async fn cache_delegation(response: &DNS, delegation: &Delegation) {
+    let nameservers: Vec<DNSResourceFormat> = response.authority.iter()
+        .flatten()
+        .filter(|record: &&DNSResourceFormat| {
+            record.rr_type == QuestionType::NS && record.name == delegation.cut
+        })
+        .cloned()
+        .collect();
+
+    rrset::set_delegation(&delegation.cut, &nameservers).await;
+
+    for target in &delegation.targets {
+        let glue: Vec<&DNSResourceFormat> = response.additional.iter()
+            .flatten()
+            .filter(|record: &&DNSResourceFormat| record.name == *target)
+            .collect();
+
+        let addresses: Vec<SocketAddr> = glue.iter()
+            .copied()
+            .filter_map(server_address)
+            .collect();
+
+        let ttl: u32 = glue.iter()
+            .map(|record: &&DNSResourceFormat| record.ttl)
+            .min()
+            .unwrap_or_default();
+
+        if !addresses.is_empty() {
+            QuestionHandler::cache_addresses(target, &addresses, ttl).await;
+        }
+    }
+}
+
+/// Nameserver address from an A or AAAA record, None for other records and
+/// for address families turned off in the config
+fn server_address(record: &DNSResourceFormat) -> Option<SocketAddr> {
+    let address: IpAddr = match record.data {
+        RData::A(address) => IpAddr::V4(address),
+        RData::AAAA(address) => IpAddr::V6(address),
+        _ => return None
+    };
+
+    CONFIG.resolver.address_family.allows(&address)
+        .then_some(SocketAddr::new(address, 53))
+}
+
+/// Address records from the additional section of the nameservers named in
+/// the authority section
+fn nameserver_addresses(response: &DNS) -> Vec<DNSResourceFormat> {
+    let nameservers: Vec<&FQDN> = response.authority.iter()
+        .flatten()
+        .filter_map(|record: &DNSResourceFormat| match &record.data {
+            RData::NS(target) => Some(target),
+            _ => None
+        })
+        .collect();
+
+    response.additional.iter()
+        .flatten()
+        .filter(|record: &&DNSResourceFormat| {
+            matches!(record.rr_type, QuestionType::A | QuestionType::AAAA) &&
+                nameservers.contains(&&record.name)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Address record types to resolve nameservers with, preferred family first
+fn address_types() -> Vec<QuestionType> {
+    match CONFIG.resolver.address_family {
+        AddressFamily::Ipv4Only => vec![QuestionType::A],
+        AddressFamily::Ipv6Only => vec![QuestionType::AAAA],
+        AddressFamily::PreferIpv6 => vec![QuestionType::AAAA, QuestionType::A],
+        AddressFamily::Dual | AddressFamily::PreferIpv4 => vec![QuestionType::A, QuestionType::AAAA]
+    }
+}
+
+/// Update the infrastructure cache with the round trip time of a useful
+/// response or with a failure
+fn record(server: &SocketAddr, rtt: Option<Duration>) {
+    if let Ok(mut cache) = INFRACACHE.lock() {
+        match rtt {
+            Some(rtt) => cache.success(server.ip(), rtt),
+            None => cache.failure(server.ip(), Duration::from_millis(CONFIG.resolver.timeout_ms))
+        }
+    }
+}
+
+/// Transaction ID of an upstream query, unpredictable unless random IDs are
+/// turned off in the config
+/// https://www.rfc-editor.org/rfc/rfc5452#section-9.2
+fn query_id() -> u16 {
+    match CONFIG.resolver.random_ids {
+        true => OsRng.gen::<u16>(),
+        false => NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// Name with the case of every letter picked at random, the server has to
+/// send it back unchanged which a spoofer can't know
+fn mix_case(name: &FQDN) -> FQDN {
+    let mut rng = rand::thread_rng();
+
+    let labels: Vec<Vec<u8>> = name.labels().iter()
+        .map(|label: &Vec<u8>| {
+            label.iter()
+                .map(|byte: &u8| match rng.gen::<bool>() {
+                    true => byte.to_ascii_uppercase(),
+                    false => byte.to_ascii_lowercase()
+                })
+                .collect()
+        })
+        .collect();
+
+    // Case doesn't change the length, so the labels are always valid
+    FQDN::from_labels(labels).unwrap_or_else(|_| name.clone())
+}
+
+/// Response acceptance against cache poisoning, the response has to answer
+/// the query that was sent and records from outside of the zone the server
+/// was asked about are removed
+/// https://www.rfc-editor.org/rfc/rfc5452#section-9.1
+///
+/// Additional section keeps only glue within the zone, it's used for
+/// nothing but the address of the next server and the additional section
+/// sent to the client, it's never cached or returned as an answer
+fn accept(response: &mut DNS, id: u16, question: &DNSQuestion, zone: &FQDN, server: &SocketAddr) -> bool {
+    let matches: bool = response.header.qr == Type::Response &&
+        response.header.id == id &&
+        match response.questions.as_deref() {
+            Some([echoed]) => {
+                echoed.name == question.name &&
+                    // Case has to be echoed exactly when it was mixed
+                    (!CONFIG.resolver.randomize_case || echoed.name.labels() == question.name.labels()) &&
+                    echoed.qtype == question.qtype &&
+                    echoed.class == question.class
+            },
+
+            _ => false
+        };
+
+    if !matches {
+        warn!(
+            LOGGER,
+            "Rejected response not matching the query";
+            "server" => server.to_string(),
+            "id" => response.header.id
+        );
+
+        return false;
+    }
+
+    for records in [&mut response.answer, &mut response.authority, &mut response.additional].into_iter().flatten() {
+        records.retain(|record: &DNSResourceFormat| {
+            let in_bailiwick: bool = record.name.is_subdomain_of(zone);
+
+            if !in_bailiwick {
+                warn!(
+                    LOGGER,
+                    "Rejected out of bailiwick record";
+                    "server" => server.to_string(),
+                    "zone" => zone.to_string(),
+                    "record" => record.to_string()
                 );
             }
+
+            in_bailiwick
+        });
+    }
+
+    true
+}
+
+/// Find records for the question in the answer section, or the alias the
+/// name leads to. DNAME is looked at first, CNAME the server synthesized
+/// from it is replaced by our own
+/// https://www.rfc-editor.org/rfc/rfc6672#section-3.2
+fn step(response: &DNS, question: &DNSQuestion) -> Step {
+    let answer: Vec<&DNSResourceFormat> = response.answer.iter()
+        .flatten()
+        .filter(|record: &&DNSResourceFormat| record.rr_class == question.class)
+        .collect();
+
+    let records: Vec<DNSResourceFormat> = answer.iter()
+        .filter(|record: &&&DNSResourceFormat| {
+            record.name == question.name &&
+                (record.rr_type == question.qtype || question.qtype == QuestionType::ANY)
+        })
+        .map(|record: &&DNSResourceFormat| (*record).clone())
+        .collect();
+
+    if !records.is_empty() {
+        return Step::Answer(records);
+    }
+
+    for record in &answer {
+        if let RData::DNAME(target) = &record.data {
+            if record.name == question.name || !question.name.is_subdomain_of(&record.name) {
+                continue;
+            }
+
+            // Owner labels are replaced with the target, too long result is YXDOMAIN
+            let prefix: usize = question.name.labels().len() - record.name.labels().len();
+            let mut labels: Vec<Vec<u8>> = question.name.labels()[..prefix].to_vec();
+            labels.extend_from_slice(target.labels());
+
+            let substituted: FQDN = match FQDN::from_labels(labels) {
+                Ok(substituted) => substituted,
+                Err(..) => return Step::TooLong((*record).clone())
+            };
+
+            let cname: DNSResourceFormat = DNSResourceFormat {
+                name: question.name.clone(),
+                rr_type: QuestionType::CNAME,
+                rr_class: record.rr_class,
+                ttl: record.ttl,
+                data: RData::CNAME(substituted.clone())
+            };
+
+            return Step::Alias(vec![(*record).clone(), cname], substituted);
+        }
+    }
+
+    if question.qtype != QuestionType::CNAME {
+        for record in &answer {
+            if let RData::CNAME(target) = &record.data {
+                if record.name == question.name {
+                    return Step::Alias(vec![(*record).clone()], target.clone());
+                }
+            }
+        }
+    }
+
+    Step::Answer(vec![])
+}
+
+/// Response that ends the walk, records in the answer section, a name error
+/// or an empty answer with the SOA record of the zone. Name errors without
+/// the SOA are final all the same, they're just not cached
+/// https://www.rfc-editor.org/rfc/rfc2308#section-2
+fn answered(response: &DNS) -> bool {
+    if response.answer.as_ref().is_some_and(|answer: &Vec<DNSResourceFormat>| !answer.is_empty()) {
+        return true;
+    }
+
+    match response.response_code() {
+        ResponseCode::NameError => true,
+        ResponseCode::NoError => negative::soa(response.authority.as_deref().unwrap_or_default()).is_some(),
+        _ => false
+    }
+}
+
+/// Response worth using, an answer or a referral down towards the name.
+/// Anything else means the server is lame for the zone or broken
+/// https://www.rfc-editor.org/rfc/rfc4697#section-2.8
+fn useful(response: &DNS, question: &DNSQuestion, zone: &FQDN) -> bool {
+    match response.response_code() {
+        ResponseCode::NoError => answered(response) || referral(response, zone, &question.name).is_some(),
+        ResponseCode::NameError => true,
+        _ => false
+    }
+}
+
+/// Zone cut, nameserver names and glue addresses from a referral in the
+/// authority section
+/// https://www.rfc-editor.org/rfc/rfc1034#section-4.3.2
+///
+/// The cut has to be strictly below the zone that was asked and at or above
+/// the queried name, otherwise following it wouldn't get any closer
+fn referral(response: &DNS, zone: &FQDN, name: &FQDN) -> Option<Delegation> {
+    let delegation: Vec<&DNSResourceFormat> = response.authority.iter()
+        .flatten()
+        .filter(|record: &&DNSResourceFormat| record.rr_type == QuestionType::NS)
+        .collect();
+
+    let cut: FQDN = delegation.first()?.name.clone();
+    if cut == *zone || !cut.is_subdomain_of(zone) || !name.is_subdomain_of(&cut) {
+        return None;
+    }
+
+    let targets: Vec<FQDN> = delegation.iter()
+        .filter(|record: &&&DNSResourceFormat| record.name == cut)
+        .filter_map(|record: &&DNSResourceFormat| match &record.data {
+            RData::NS(target) => Some(target.clone()),
+            _ => None
+        })
+        .collect();
+
+    if targets.is_empty() {
+        return None;
+    }
+
+    let glue: Vec<SocketAddr> = response.additional.iter()
+        .flatten()
+        .filter(|record: &&DNSResourceFormat| targets.contains(&record.name))
+        .filter_map(server_address)
+        .collect();
+
+    Some(Delegation {
+        cut,
+        targets,
+        glue
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::rdata::StartOfAuthority;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn name(value: &str) -> FQDN {
+        return FQDN::try_from(value.to_string()).unwrap();
+    }
+
+    fn record(owner: &str, ttl: u32, data: RData) -> DNSResourceFormat {
+        return DNSResourceFormat {
+            name: name(owner),
+            rr_type: match data {
+                RData::A(..) => QuestionType::A,
+                RData::AAAA(..) => QuestionType::AAAA,
+                RData::NS(..) => QuestionType::NS,
+                RData::CNAME(..) => QuestionType::CNAME,
+                RData::DNAME(..) => QuestionType::DNAME,
+                _ => QuestionType::SOA
+            },
+            rr_class: QuestionClass::IN,
+            ttl,
+            data
+        };
+    }
+
+    fn soa(zone: &str) -> DNSResourceFormat {
+        return record(zone, 3600, RData::SOA(StartOfAuthority {
+            mname: name("ns.example.com"),
+            rname: name("hostmaster.example.com"),
+            serial: 1,
+            refresh: 7200,
+            retry: 900,
+            expire: 1209600,
+            minimum: 300
+        }));
+    }
+
+    fn question(value: &str, qtype: QuestionType) -> DNSQuestion {
+        return DNSQuestion {
+            name: name(value),
+            qtype,
+            class: QuestionClass::IN
+        };
+    }
+
+    /// Response to `question` with the given sections, empty ones left out
+    fn response(
+        question: &DNSQuestion,
+        code: ResponseCode,
+        answer: Vec<DNSResourceFormat>,
+        authority: Vec<DNSResourceFormat>,
+        additional: Vec<DNSResourceFormat>
+    ) -> DNS {
+        let mut response: DNS = DNS::new();
+        response.header.qr = Type::Response;
+        response.header.id = 0x1234;
+        response.questions = Some(vec![question.clone()]);
+        response.set_response_code(code);
+
+        for (section, records) in [
+            (&mut response.answer, answer),
+            (&mut response.authority, authority),
+            (&mut response.additional, additional)
+        ] {
+            if !records.is_empty() {
+                *section = Some(records);
+            }
+        }
+
+        return response;
+    }
+
+    #[test]
+    fn step_finds_answers_and_aliases() {
+        let asked: DNSQuestion = question("www.example.com", QuestionType::A);
+        let address: DNSResourceFormat = record("www.example.com", 60, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+        let alias: DNSResourceFormat = record("www.example.com", 60, RData::CNAME(name("web.example.com")));
+
+        let table: Vec<(Vec<DNSResourceFormat>, usize, Option<&str>)> = vec![
+            (vec![address.clone()], 1, None),
+            (vec![alias.clone()], 1, Some("web.example.com")),
+
+            // Records of the name win over an alias that shouldn't be there
+            (vec![alias.clone(), address.clone()], 1, None),
+
+            // Records of other names and classes are no answer
+            (vec![record("mail.example.com", 60, RData::A(Ipv4Addr::LOCALHOST))], 0, None),
+            (vec![DNSResourceFormat { rr_class: QuestionClass::CH, ..address.clone() }], 0, None),
+            (vec![], 0, None)
+        ];
+
+        for (answer, count, target) in table {
+            match (step(&response(&asked, ResponseCode::NoError, answer, vec![], vec![]), &asked), target) {
+                (Step::Answer(records), None) => assert_eq!(records.len(), count),
+                (Step::Alias(records, found), Some(target)) => {
+                    assert_eq!(records.len(), count);
+                    assert_eq!(found, name(target));
+                },
+                _ => panic!("unexpected step for {:?}", target)
+            }
+        }
+
+        // CNAME is the answer when it's what was asked for
+        let asked: DNSQuestion = question("www.example.com", QuestionType::CNAME);
+        let found: Step = step(&response(&asked, ResponseCode::NoError, vec![alias], vec![], vec![]), &asked);
+        assert!(matches!(found, Step::Answer(records) if records.len() == 1));
+    }
+
+    #[test]
+    fn step_synthesizes_cname_from_dname() {
+        let asked: DNSQuestion = question("www.sub.example.com", QuestionType::A);
+        let dname: DNSResourceFormat = record("example.com", 300, RData::DNAME(name("example.net")));
+
+        // CNAME of the server is replaced by ours, even with another TTL
+        let synthesized: DNSResourceFormat = record("www.sub.example.com", 0, RData::CNAME(name("elsewhere.example.org")));
+        let answer: DNS = response(&asked, ResponseCode::NoError, vec![synthesized, dname.clone()], vec![], vec![]);
+
+        let Step::Alias(records, target) = step(&answer, &asked) else {
+            panic!("DNAME was not followed");
         };
 
-        println!("{:?}", pkt);
+        assert_eq!(target, name("www.sub.example.net"));
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].data, dname.data);
+        assert_eq!(records[1].name, asked.name);
+        assert_eq!(records[1].ttl, 300);
+        assert_eq!(records[1].data, RData::CNAME(name("www.sub.example.net")));
+
+        // DNAME doesn't apply to its owner name itself
+        let asked: DNSQuestion = question("example.com", QuestionType::A);
+        let answer: DNS = response(&asked, ResponseCode::NoError, vec![dname], vec![], vec![]);
+        assert!(matches!(step(&answer, &asked), Step::Answer(records) if records.is_empty()));
+    }
+
+    #[test]
+    fn step_reports_too_long_substitution() {
+        // 201 octets asked, the target is 64 octets longer than the owner
+        let label: String = "a".repeat(63);
+        let asked: DNSQuestion = question(&format!("{0}.{0}.{0}.example", label), QuestionType::A);
+        let dname: DNSResourceFormat = record("example", 300, RData::DNAME(name(&format!("{}.net", "b".repeat(63)))));
+
+        let answer: DNS = response(&asked, ResponseCode::NoError, vec![dname.clone()], vec![], vec![]);
+        let Step::TooLong(record) = step(&answer, &asked) else {
+            panic!("substitution was not too long");
+        };
 
-        todo!();
+        assert_eq!(record.name, dname.name);
+        assert_eq!(record.data, dname.data);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn referral_cut_is_between_zone_and_name() {
+        let name_asked: FQDN = name("www.example.com");
+        let asked: DNSQuestion = question("www.example.com", QuestionType::A);
+        let ns = |owner: &str, target: &str| record(owner, 172800, RData::NS(name(target)));
+
+        // Zone asked, nameservers in the authority section and the cut if it's followed
+        let table: Vec<(&str, Vec<DNSResourceFormat>, Option<&str>)> = vec![
+            (".", vec![ns("com", "a.gtld-servers.net")], Some("com")),
+            ("com", vec![ns("example.com", "ns1.example.com")], Some("example.com")),
+            (".", vec![ns("www.example.com", "ns1.example.com")], Some("www.example.com")),
+
+            // Same zone again, a parent of it or a name outside of the cut
+            ("com", vec![ns("com", "a.gtld-servers.net")], None),
+            ("example.com", vec![ns("com", "a.gtld-servers.net")], None),
+            ("com", vec![ns("example.net", "ns1.example.net")], None),
+            ("com", vec![], None)
+        ];
+
+        for (zone, authority, cut) in table {
+            let found: Option<Delegation> = referral(
+                &response(&asked, ResponseCode::NoError, vec![], authority, vec![]),
+                &name(zone),
+                &name_asked
+            );
+
+            assert_eq!(found.map(|delegation: Delegation| delegation.cut), cut.map(name), "zone {}", zone);
+        }
+    }
+
+    #[test]
+    fn referral_takes_glue_of_its_targets() {
+        let asked: DNSQuestion = question("www.example.com", QuestionType::A);
+        let authority: Vec<DNSResourceFormat> = vec![
+            record("example.com", 3600, RData::NS(name("ns1.example.com"))),
+            record("example.com", 3600, RData::NS(name("ns2.example.com"))),
+
+            // Other owners are no targets of the cut
+            record("other.example.com", 3600, RData::NS(name("ns3.example.com")))
+        ];
+        let additional: Vec<DNSResourceFormat> = vec![
+            record("ns1.example.com", 3600, RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            record("ns2.example.com", 3600, RData::AAAA(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2))),
+            record("ns3.example.com", 3600, RData::A(Ipv4Addr::new(192, 0, 2, 3))),
+            record("www.example.com", 3600, RData::A(Ipv4Addr::new(192, 0, 2, 4)))
+        ];
+
+        let delegation: Delegation = referral(
+            &response(&asked, ResponseCode::NoError, vec![], authority, additional),
+            &name("com"),
+            &asked.name
+        ).unwrap();
+
+        assert_eq!(delegation.targets, vec![name("ns1.example.com"), name("ns2.example.com")]);
+        assert_eq!(delegation.glue, vec![
+            "192.0.2.1:53".parse::<SocketAddr>().unwrap(),
+            "[2001:db8::2]:53".parse::<SocketAddr>().unwrap()
+        ]);
+    }
+
+    #[test]
+    fn answered_and_useful_responses() {
+        let asked: DNSQuestion = question("www.example.com", QuestionType::A);
+        let zone: FQDN = name("com");
+        let address: DNSResourceFormat = record("www.example.com", 60, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+        let delegation: DNSResourceFormat = record("example.com", 3600, RData::NS(name("ns1.example.com")));
+
+        // Response code, answer, authority, whether it's final and whether it's useful
+        type Row = (ResponseCode, Vec<DNSResourceFormat>, Vec<DNSResourceFormat>, bool, bool);
+        let table: Vec<Row> = vec![
+            (ResponseCode::NoError, vec![address.clone()], vec![], true, true),
+            (ResponseCode::NoError, vec![], vec![soa("example.com")], true, true),
+            (ResponseCode::NoError, vec![], vec![delegation.clone()], false, true),
+            (ResponseCode::NameError, vec![], vec![soa("example.com")], true, true),
+            (ResponseCode::NameError, vec![], vec![], true, true),
+
+            // Lame, it's neither an answer nor a step closer
+            (ResponseCode::NoError, vec![], vec![], false, false),
+            (ResponseCode::NoError, vec![], vec![record("com", 3600, RData::NS(name("a.gtld-servers.net")))], false, false),
+
+            (ResponseCode::ServerFailure, vec![], vec![], false, false),
+            (ResponseCode::Refused, vec![], vec![delegation], false, false)
+        ];
+
+        for (code, answer, authority, is_answered, is_useful) in table {
+            let response: DNS = response(&asked, code, answer, authority, vec![]);
+
+            assert_eq!(answered(&response), is_answered, "{:?}", code);
+            assert_eq!(useful(&response, &asked, &zone), is_useful, "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn accept_matches_the_query() {
+        let asked: DNSQuestion = question("www.example.com", QuestionType::A);
+        let zone: FQDN = name("example.com");
+        let server: SocketAddr = "192.0.2.53:53".parse().unwrap();
+        let matching = || response(&asked, ResponseCode::NoError, vec![], vec![], vec![]);
+
+        let mut query: DNS = matching();
+        query.header.qr = Type::Query;
+
+        let mut other_id: DNS = matching();
+        other_id.header.id = 0x4321;
+
+        let mut none: DNS = matching();
+        none.questions = None;
+
+        let mut two: DNS = matching();
+        two.questions = Some(vec![asked.clone(), asked.clone()]);
+
+        let table: Vec<(DNS, bool)> = vec![
+            (matching(), true),
+            (query, false),
+            (other_id, false),
+            (none, false),
+            (two, false),
+            (response(&question("mail.example.com", QuestionType::A), ResponseCode::NoError, vec![], vec![], vec![]), false),
+            (response(&question("www.example.com", QuestionType::AAAA), ResponseCode::NoError, vec![], vec![], vec![]), false),
+            (response(&DNSQuestion { class: QuestionClass::CH, ..asked.clone() }, ResponseCode::NoError, vec![], vec![], vec![]), false),
+
+            // Case only has to be echoed exactly when it was mixed
+            (
+                response(&question("WWW.example.COM", QuestionType::A), ResponseCode::NoError, vec![], vec![], vec![]),
+                !CONFIG.resolver.randomize_case
+            )
+        ];
+
+        for (mut response, accepted) in table {
+            assert_eq!(accept(&mut response, 0x1234, &asked, &zone, &server), accepted);
+        }
+    }
+
+    #[test]
+    fn accept_removes_records_outside_of_the_zone() {
+        let asked: DNSQuestion = question("www.example.com", QuestionType::A);
+        let server: SocketAddr = "192.0.2.53:53".parse().unwrap();
+
+        let mut response: DNS = response(
+            &asked,
+            ResponseCode::NoError,
+            vec![
+                record("www.example.com", 60, RData::CNAME(name("www.example.net"))),
+                record("www.example.net", 60, RData::A(Ipv4Addr::new(203, 0, 113, 1)))
+            ],
+            vec![
+                record("example.com", 3600, RData::NS(name("ns1.example.com"))),
+                record("com", 3600, RData::NS(name("ns.attacker.example")))
+            ],
+            vec![
+                record("ns1.example.com", 3600, RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+                record("ns.attacker.example", 3600, RData::A(Ipv4Addr::new(203, 0, 113, 2)))
+            ]
+        );
+
+        assert!(accept(&mut response, 0x1234, &asked, &name("example.com"), &server));
+
+        for (section, owner) in [
+            (&response.answer, "www.example.com"),
+            (&response.authority, "example.com"),
+            (&response.additional, "ns1.example.com")
+        ] {
+            let records: &Vec<DNSResourceFormat> = section.as_ref().unwrap();
+
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].name.labels(), name(owner).labels());
+        }
+    }
+
+    #[test]
+    fn mix_case_only_changes_case() {
+        let original: FQDN = name("_dmarc-1.subdomain.example.com");
+        let mut changed: bool = false;
+
+        for _ in 0..16 {
+            let mixed: FQDN = mix_case(&original);
+
+            assert_eq!(mixed, original);
+            assert_eq!(mixed.len(), original.len());
+            assert_eq!(mixed.to_string().to_lowercase(), original.to_string());
+
+            changed |= mixed.labels() != original.labels();
+        }
+
+        // All sixteen coming out lowercase has a chance of 2^-384
+        assert!(changed);
+        assert_eq!(mix_case(&FQDN::new()), FQDN::new());
+    }
+}
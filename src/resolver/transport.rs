@@ -1,27 +1,38 @@
 use crate::helpers::bit::prepend;
-#[allow(dead_code)]
-
-use crate::{
-    parser::dns::DNS,
+pub use crate::parser::dns::TransportProto;
+use crate::parser::dns::DNS;
+use crate::CONFIG;
+use rustdns::{
     convert_u16_to_two_u8s,
     convert_two_u8s_to_u16
 };
-use async_recursion::async_recursion;
-use std::io::{Read, Write};
-use std::{net::{
-    UdpSocket, 
-    SocketAddr, 
-    Ipv4Addr, 
-    IpAddr, TcpStream
-}};
+use rand::{rngs::OsRng, Rng};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::time::Duration;
+use std::net::{
+    SocketAddr,
+    Ipv4Addr,
+    Ipv6Addr,
+    IpAddr
+};
+use tokio::io::{
+    AsyncReadExt,
+    AsyncWriteExt
+};
+use tokio::net::{
+    UdpSocket,
+    TcpStream
+};
 
 enum_from_primitive! {
     #[repr(u8)]
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq)]
+    #[allow(clippy::enum_variant_names)]
     pub enum TransportError {
         /*
-            Client cannot be instantiated probably because ip or port is 
-            already taken or this process does not have permissions to 
+            Client cannot be instantiated probably because ip or port is
+            already taken or this process does not have permissions to
             use the port
         */
         ClientInstantiateError = 0x0,
@@ -33,14 +44,160 @@ enum_from_primitive! {
         WriteError = 0x3,
 
         // Datagram length is smaller than it should be
-        DatagramLengthError = 0x4
+        DatagramLengthError = 0x4,
+
+        // Server didn't answer in any of the attempts
+        TimeoutError = 0x5,
+
+        /*
+            Server refused the connection, for UDP this is the ICMP port
+            unreachable message, retrying won't help with either
+        */
+        RefusedError = 0x6
+    }
+}
+
+/// Ports below this are privileged or used by well known services
+const MIN_RANDOM_PORT: u16 = 1024;
+
+/// Random ports tried before leaving the choice to the OS
+const PORT_ATTEMPTS: usize = 8;
+
+/// Smallest message that can hold a header
+const MIN_MESSAGE_LENGTH: usize = 12;
+
+/// Bind the socket for one upstream query on the unspecified address of
+/// the same family as `host`, on a random port if enabled so a spoofer has
+/// to guess the port as well as the ID
+/// https://www.rfc-editor.org/rfc/rfc5452#section-9.2
+async fn bind_socket(host: SocketAddr) -> Result<UdpSocket, TransportError> {
+    let local: IpAddr = match host {
+        SocketAddr::V4(..) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(..) => IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    };
+
+    if CONFIG.resolver.random_ports {
+        for _ in 0..PORT_ATTEMPTS {
+            let port: u16 = OsRng.gen_range(MIN_RANDOM_PORT..=u16::MAX);
+
+            // Port can be taken by another socket, another one is tried then
+            if let Ok(socket) = UdpSocket::bind(SocketAddr::new(local, port)).await {
+                return Ok(socket);
+            }
+        }
+    }
+
+    // Port 0 means OS will assign available port on it's own
+    UdpSocket::bind(SocketAddr::new(local, 0))
+        .await
+        .map_err(|_| TransportError::ClientInstantiateError)
+}
+
+/// Refused connection gets its own error, everything else is `otherwise`
+fn io_error(error: std::io::Error, otherwise: TransportError) -> TransportError {
+    match error.kind() {
+        ErrorKind::ConnectionRefused => TransportError::RefusedError,
+        _ => otherwise
     }
 }
 
-#[derive(PartialEq, Debug)]
-pub enum TransportProto {
-    TCP,
-    UDP
+/// Run `exchange` until it succeeds, every attempt gets twice the time of
+/// the previous one. Refused connections are not retried
+/// https://www.ietf.org/rfc/rfc1035.html#section-4.2.1
+async fn retry<F, Fut>(mut exchange: F) -> Result<DNS, TransportError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<DNS, TransportError>>
+{
+    let mut timeout: Duration = Duration::from_millis(CONFIG.resolver.timeout_ms);
+    let mut error: TransportError = TransportError::TimeoutError;
+
+    for _ in 0..CONFIG.resolver.attempts.max(1) {
+        match tokio::time::timeout(timeout, exchange()).await {
+            Ok(Ok(datagram)) => return Ok(datagram),
+            Ok(Err(TransportError::RefusedError)) => return Err(TransportError::RefusedError),
+            Ok(Err(e)) => error = e,
+            Err(..) => error = TransportError::TimeoutError
+        }
+
+        timeout *= 2;
+    }
+
+    Err(error)
+}
+
+/// One UDP query and its response
+async fn udp_exchange(payload: &[u8], host: SocketAddr) -> Result<DNS, TransportError> {
+    let socket: UdpSocket = bind_socket(host).await?;
+
+    /*
+        Connected socket only receives datagrams sent from the host,
+        replies from any other address are dropped by the OS
+    */
+    socket.connect(host)
+        .await
+        .map_err(|_| TransportError::ClientInstantiateError)?;
+
+    socket.send(payload)
+        .await
+        .map_err(|e| io_error(e, TransportError::WriteError))?;
+
+    /*
+        Servers don't always keep to the size the query advertises, and the OS
+        cuts datagrams that don't fit the buffer without telling, so the buffer
+        holds the largest datagram there can be
+    */
+    let mut buf: Vec<u8> = vec![0; u16::MAX as usize];
+    let received: usize = socket.recv(&mut buf)
+        .await
+        .map_err(|e| io_error(e, TransportError::ReadError))?;
+
+    if received < MIN_MESSAGE_LENGTH {
+        return Err(TransportError::DatagramLengthError);
+    }
+
+    // Upstream replies are as untrusted as client queries
+    DNS::from(&buf[0..received], TransportProto::UDP)
+        .map_err(|_| TransportError::ReadError)
+}
+
+/// One TCP connection with a single query, the response is read until the
+/// whole length from its prefix arrived
+/// https://www.ietf.org/rfc/rfc1035.html#section-4.2.2
+async fn tcp_exchange(payload: &[u8], host: SocketAddr) -> Result<DNS, TransportError> {
+    let length: u16 = u16::try_from(payload.len())
+        .map_err(|_| TransportError::WriteError)?;
+
+    let mut stream: TcpStream = TcpStream::connect(host)
+        .await
+        .map_err(|e| io_error(e, TransportError::ClientInstantiateError))?;
+
+    let u8_len: [u8; 2] = convert_u16_to_two_u8s!(length, u16);
+    stream.write_all(&prepend(payload.to_vec(), &u8_len))
+        .await
+        .map_err(|e| io_error(e, TransportError::WriteError))?;
+
+    let mut prefix: [u8; 2] = [0; 2];
+    stream.read_exact(&mut prefix)
+        .await
+        .map_err(|e| io_error(e, TransportError::ReadError))?;
+
+    let buf_len: usize = convert_two_u8s_to_u16!(prefix[0], prefix[1]) as usize;
+    if buf_len < MIN_MESSAGE_LENGTH {
+        return Err(TransportError::DatagramLengthError);
+    }
+
+    // Message can come in any number of segments
+    let mut message: Vec<u8> = vec![0; buf_len];
+    stream.read_exact(&mut message)
+        .await
+        .map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => TransportError::DatagramLengthError,
+            _ => io_error(e, TransportError::ReadError)
+        })?;
+
+    DNS::from(&prepend(message, &prefix), TransportProto::TCP)
+        .map_err(|_| TransportError::ReadError)
 }
 
 /// This helper transport function is used to send payload with either TCP or UDP
@@ -49,119 +206,151 @@ pub enum TransportProto {
 /// UDP will be used first, if the DNS message is truncated, then TCP will be used.
 /// That means response latency will be significantly longer if TCP is required to be used.
 ///
+/// Every attempt has a timeout from the config and failed attempts are retried,
+/// see `retry`
+///
 /// Returns DNS struct on success and Transport on error
-#[async_recursion]
 pub async fn onetime_transport(payload: &[u8], host: SocketAddr, proto: Option<TransportProto>) -> Result<DNS, TransportError> {
     match proto {
         Some(TransportProto::UDP) | None => {
+            let datagram: DNS = retry(|| udp_exchange(payload, host)).await?;
+
             /*
-                We are creating the socket on port 0, which means OS will assign 
-                available port on it's own
+                Check if message length is bigger than this actual datagram length.
+                This is the whole purpose of this function.
             */
-            let mut socket: Result<std::net::UdpSocket, std::io::Error> = UdpSocket::bind(
-                SocketAddr::new(
-                    IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0
-                )
-            );
-
-            if socket.is_err() {
-                return Err::<DNS, TransportError>(
-                    TransportError::ClientInstantiateError
-                );
-            };
-
-            let transport: Result<usize, std::io::Error> = socket.as_mut().unwrap().send_to(
-                payload, 
-                host
-            );
-
-            if transport.is_err() {
-                if proto == Some(TransportProto::UDP) {
-                    return onetime_transport(
-                        payload, 
-                        host, 
-                        Some(TransportProto::TCP)
-                    ).await;
-                }
-
-                return Err::<DNS, TransportError>(
-                    TransportError::WriteError
-                );
+            if datagram.header.truncated {
+                return retry(|| tcp_exchange(payload, host)).await;
             }
 
-            let mut buf: [u8; 512] = [1; 512];
-            socket.unwrap().peek(&mut buf)
-                .expect("Failed");
+            Ok(datagram)
+        },
 
-            let datagram = DNS::from(&buf, TransportProto::UDP);
+        Some(TransportProto::TCP) => retry(|| tcp_exchange(payload, host)).await
+    }
+}
 
-            /* 
-                Check if message length is bigger than this actual datagram length.
-                This is the whole purpose of this function.
-            */
-            if datagram.unwrap().header.truncated {
-                return onetime_transport(
-                    payload, 
-                    host, 
-                    Some(TransportProto::TCP)
-                ).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::r#type::Type;
+    use tokio::net::TcpListener;
+
+    /// Response with just a header, as the server sends it without its prefix
+    fn response(id: u16) -> Vec<u8> {
+        let mut datagram: DNS = DNS::new();
+        datagram.header.id = id;
+        datagram.header.qr = Type::Response;
+
+        return datagram.bytes().unwrap();
+    }
+
+    /// Server that reads one prefixed query and writes each of `chunks` with
+    /// a pause in between, then closes the connection
+    async fn server(chunks: Vec<Vec<u8>>) -> SocketAddr {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address: SocketAddr = listener.local_addr().unwrap();
+
+        tokio::task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut prefix: [u8; 2] = [0; 2];
+            stream.read_exact(&mut prefix).await.unwrap();
+            let mut query: Vec<u8> = vec![0; u16::from_be_bytes(prefix) as usize];
+            stream.read_exact(&mut query).await.unwrap();
+
+            for chunk in chunks {
+                stream.write_all(&chunk).await.unwrap();
+                stream.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
             }
-        },
+        });
+
+        return address;
+    }
 
-        Some(TransportProto::TCP) => {
-            let mut stream: std::io::Result<TcpStream> = TcpStream::connect(host);
-
-            if stream.is_err() {
-                return Err::<DNS, TransportError>(
-                    TransportError::ClientInstantiateError   
-                );
-            };
-
-            let u8_len: [u8; 2] = convert_u16_to_two_u8s!(payload.len() as u16, u16);
-            let vec_u8 = prepend(payload.to_vec(), &u8_len);
-            let write_str = stream.as_mut()
-                .unwrap()
-                .write(
-                    &*vec_u8
-                );
-
-            if write_str.is_err() {
-                return Err::<DNS, TransportError>(
-                    TransportError::WriteError
-                );
-            };
-
-            let mut buf: [u8; 2048] = [1; 2048];
-            let read_str: Result<usize, std::io::Error> = stream.unwrap().read(&mut buf);
-
-            if read_str.is_err() {
-                return Err::<DNS, TransportError>(
-                    TransportError::ReadError
-                );
-            };
-
-            // Remove unused bytes from the byte slice
-            let sliced: &[u8] = &buf[0..read_str.unwrap()];
+    #[tokio::test]
+    async fn tcp_response_split_across_reads() {
+        let message: Vec<u8> = response(0xbeef);
+        let length: [u8; 2] = (message.len() as u16).to_be_bytes();
 
-            /*
-                Since datagram can't be parsed, because we don't know if we
-                have the whole datagram, length will be extracted from the 
-                the first two bytes of the buffer
-            */
-            let buf_len = convert_two_u8s_to_u16!(sliced[0], sliced[1]);
-
-            // Check if datagram arrived in correct length
-            if buf_len != (sliced.as_ref().len() as u16 - 2) {
-                return Err::<DNS, TransportError>(
-                    TransportError::DatagramLengthError
-                );
-            };
-
-            let datagram = DNS::from(sliced, TransportProto::TCP);
-            
-            return Ok(datagram.unwrap());
+        // Prefix split in two and the message in two more segments
+        let address: SocketAddr = server(vec![
+            vec![length[0]],
+            vec![length[1]],
+            message[..5].to_vec(),
+            message[5..].to_vec()
+        ]).await;
+
+        let datagram: DNS = tcp_exchange(&response(1), address).await.unwrap();
+        assert_eq!(datagram.header.id, 0xbeef);
+    }
+
+    #[tokio::test]
+    async fn tcp_short_and_cut_responses() {
+        let message: Vec<u8> = response(0xbeef);
+
+        // Response code of the exchange by what the server sends before closing
+        let table: Vec<(Vec<Vec<u8>>, TransportError)> = vec![
+            (vec![], TransportError::ReadError),
+            (vec![vec![0x00]], TransportError::ReadError),
+            (vec![vec![0x00, 0x05], vec![0; 5]], TransportError::DatagramLengthError),
+            (vec![vec![0x00, 0x40], message.clone()], TransportError::DatagramLengthError)
+        ];
+
+        for (chunks, error) in table {
+            let address: SocketAddr = server(chunks).await;
+            assert_eq!(tcp_exchange(&response(1), address).await.err(), Some(error));
         }
     }
 
-    Err(TransportError::ReadError)
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn refused_connections_are_not_retried() {
+        let mut calls: usize = 0;
+        let result: Result<DNS, TransportError> = retry(|| {
+            calls += 1;
+            async { Err(TransportError::RefusedError) }
+        }).await;
+
+        assert_eq!(result.err(), Some(TransportError::RefusedError));
+        assert_eq!(calls, 1);
+
+        // Other errors are retried for every attempt and the last one is kept
+        let mut calls: usize = 0;
+        let result: Result<DNS, TransportError> = retry(|| {
+            calls += 1;
+            async { Err(TransportError::ReadError) }
+        }).await;
+
+        assert_eq!(result.err(), Some(TransportError::ReadError));
+        assert_eq!(calls, CONFIG.resolver.attempts.max(1) as usize);
+
+        // Nothing listens on the port of a closed listener
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address: SocketAddr = listener.local_addr().unwrap();
+        drop(listener);
+
+        assert_eq!(tcp_exchange(&response(1), address).await.err(), Some(TransportError::RefusedError));
+    }
+
+    #[tokio::test]
+    async fn attempts_get_twice_the_time() {
+        let started: std::time::Instant = std::time::Instant::now();
+        let mut calls: usize = 0;
+
+        // First attempt times out, the second one would have made it in time
+        let result: Result<DNS, TransportError> = retry(|| {
+            calls += 1;
+            let wait: Duration = Duration::from_millis(CONFIG.resolver.timeout_ms * 3 / 2);
+
+            async move {
+                tokio::time::sleep(wait).await;
+                Ok(DNS::new())
+            }
+        }).await;
+
+        assert!(result.is_ok());
+        assert_eq!(calls, 2);
+        assert!(started.elapsed() >= Duration::from_millis(CONFIG.resolver.timeout_ms * 5 / 2));
+    }
+}